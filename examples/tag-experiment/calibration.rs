@@ -0,0 +1,157 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde_json::{Map, Value};
+use tag_game::{Candidate, Method};
+
+use crate::{csv_field, experiment::Experiment, replicate, Format};
+
+/// Writes every candidate with its parameters, statistics and distance.
+fn write(
+    writer: &mut impl Write,
+    format: Format,
+    parameters: &[&String],
+    metrics: &[&'static str],
+    candidates: &[Candidate],
+) -> io::Result<()> {
+    if format == Format::Csv {
+        let header = Some("rank")
+            .into_iter()
+            .chain(parameters.iter().map(|name| name.as_str()))
+            .chain(metrics.iter().copied())
+            .chain(Some("distance"))
+            .map(csv_field)
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", header.join(","))?;
+    }
+    for (rank, candidate) in candidates.iter().enumerate() {
+        match format {
+            Format::Csv => {
+                let fields = Some((rank + 1).to_string())
+                    .into_iter()
+                    .chain(candidate.parameters.iter().map(ToString::to_string))
+                    .chain(candidate.statistics.iter().map(ToString::to_string))
+                    .chain(Some(candidate.distance.to_string()))
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+            Format::Jsonl => {
+                let mut object = Map::new();
+                object.insert("rank".to_string(), (rank + 1).into());
+                for (name, value) in parameters.iter().zip(&candidate.parameters) {
+                    object.insert((*name).clone(), Value::from(*value));
+                }
+                for (name, value) in metrics.iter().zip(&candidate.statistics) {
+                    object.insert((*name).to_string(), Value::from(*value));
+                }
+                object.insert("distance".to_string(), Value::from(candidate.distance));
+                serde_json::to_writer(&mut *writer, &object)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Searches the parameters, whose final metrics are closest to the targets, and writes the
+/// candidates and the configuration of the best one.
+#[allow(clippy::cast_precision_loss)]
+pub fn run(
+    experiment: &Experiment,
+    output: &Path,
+    format: Format,
+    extension: &str,
+) -> io::Result<()> {
+    let spec = experiment
+        .calibration
+        .as_ref()
+        .expect("the experiment describes a calibration");
+    let names = experiment.parameter_names();
+    let metrics = spec.targets.keys().copied().collect::<Vec<_>>();
+    let parameters = |values: &[f64]| {
+        names
+            .iter()
+            .map(|&name| name.clone())
+            .zip(values.iter().map(|&value| Value::from(value)))
+            .collect::<Vec<_>>()
+    };
+    // Check the parameters once, so the search does not fail later
+    let lower = spec.factors.values().map(|range| range.start);
+    experiment.config(&parameters(&lower.collect::<Vec<_>>()))?;
+
+    let calibration = spec.calibration(experiment.seed);
+    eprintln!(
+        "Calibrating {} parameters to {} targets with {} replications each",
+        names.len(),
+        metrics.len(),
+        experiment.replications
+    );
+    let fit = calibration.run(|values, _seed| {
+        // Every candidate plays the same games, so they are compared on equal terms
+        let config = experiment
+            .config(&parameters(values))
+            .expect("the parameters were checked before");
+        let finals = (0..experiment.replications)
+            .filter_map(|replication| {
+                replicate(experiment, &metrics, 0, &config, replication).pop()
+            })
+            .collect::<Vec<_>>();
+        (0..metrics.len())
+            .map(|index| {
+                let values = finals
+                    .iter()
+                    .filter_map(|row| row.values[index])
+                    .collect::<Vec<_>>();
+                if values.is_empty() {
+                    f64::NAN
+                } else {
+                    values.iter().sum::<f64>() / values.len() as f64
+                }
+            })
+            .collect()
+    });
+
+    let metric_names = metrics
+        .iter()
+        .map(|metric| metric.name())
+        .collect::<Vec<_>>();
+    let path = output.join(format!("calibration.{}", extension));
+    let mut writer = BufWriter::new(File::create(&path)?);
+    write(&mut writer, format, &names, &metric_names, &fit.candidates)?;
+    writer.flush()?;
+
+    let best = fit.best();
+    println!(
+        "Best fit after {} evaluations (distance {:.4}):",
+        fit.evaluations, best.distance
+    );
+    for (name, value) in names.iter().zip(&best.parameters) {
+        println!("  {} = {:.4}", name, value);
+    }
+    for ((metric, value), target) in metrics
+        .iter()
+        .zip(&best.statistics)
+        .zip(spec.targets.values())
+    {
+        println!("  {}: {:.4} (target {})", metric.name(), value, target);
+    }
+    if let Method::Rejection { .. } = calibration.method() {
+        println!("Posterior mean of {} samples:", fit.candidates.len());
+        for (name, value) in names.iter().zip(fit.mean()) {
+            println!("  {} = {:.4}", name, value);
+        }
+    }
+
+    let config_path = output.join("calibrated.json");
+    let config = experiment.config(&parameters(&best.parameters))?;
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&config_path)?), &config)?;
+    eprintln!(
+        "Wrote the candidates to {} and the best configuration to {}",
+        path.display(),
+        config_path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,309 @@
+use std::{
+    cmp::Ordering,
+    io::{self, Write},
+    ops::Range,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::{
+    agent::Properties,
+    config::AgentConfig,
+    csv_field, csv_value,
+    experiment::{Evolution, Experiment, Run, Tracker},
+    world::create_simulation,
+    Format,
+};
+
+/// The names of the evolved properties.
+const GENES: [&str; 4] = [
+    "untagged_deciding",
+    "tagged_deciding",
+    "untagged_speed_multiplied",
+    "tagged_speed_multiplied",
+];
+
+type Genome = [f64; 4];
+
+fn genome(properties: &Properties) -> Genome {
+    [
+        properties.untagged_deciding,
+        properties.tagged_deciding,
+        f64::from(properties.untagged_speed_multiplied),
+        f64::from(properties.tagged_speed_multiplied),
+    ]
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn properties(genome: Genome) -> Properties {
+    Properties {
+        untagged_deciding: genome[0],
+        tagged_deciding: genome[1],
+        untagged_speed_multiplied: genome[2] as f32,
+        tagged_speed_multiplied: genome[3] as f32,
+    }
+}
+
+fn ranges(config: &AgentConfig) -> [Range<f64>; 4] {
+    let widen = |range: &Range<f32>| f64::from(range.start)..f64::from(range.end);
+    [
+        config.untagged_deciding.clone(),
+        config.tagged_deciding.clone(),
+        widen(&config.untagged_speed_multiplied),
+        widen(&config.tagged_speed_multiplied),
+    ]
+}
+
+/// The statistics of one generation.
+pub struct Generation {
+    run: usize,
+    generation: usize,
+    mean_fitness: f64,
+    best_fitness: f64,
+    mean_tags: f64,
+    /// The mean and the standard deviation of every gene.
+    genes: [(f64, f64); 4],
+}
+
+/// Plays one game with the given properties and returns the fitness of every player, which is
+/// the fraction of ticks the player was not "It", and the number of tags.
+#[allow(clippy::cast_precision_loss)]
+fn play(experiment: &Experiment, run: &Run, population: &[Genome], seed: u64) -> (Vec<f64>, u64) {
+    let mut simulation = create_simulation(&run.config, seed);
+    simulation.set_history_capacity(0);
+    for ((_, state), genome) in simulation.agents_mut().iter_mut().zip(population) {
+        state.properties = properties(*genome);
+    }
+    let mut tracker = Tracker::new(&simulation);
+    while !experiment.stop.reached(&simulation, &tracker) {
+        simulation.update();
+        tracker.observe(&simulation);
+    }
+    let ticks = simulation.tick().max(1) as f64;
+    let fitness = tracker
+        .ticks_as_it(&simulation)
+        .iter()
+        .map(|&it| 1. - it as f64 / ticks)
+        .collect();
+    (fitness, tracker.tags())
+}
+
+/// Returns the index of the fittest of `size` random players.
+fn tournament(rng: &mut StdRng, fitness: &[f64], size: usize) -> usize {
+    (0..size)
+        .map(|_| rng.gen_range(0..fitness.len()))
+        .max_by(|&a, &b| {
+            fitness[a]
+                .partial_cmp(&fitness[b])
+                .unwrap_or(Ordering::Equal)
+        })
+        .expect("the tournament has at least one player")
+}
+
+/// Breeds the next generation by selection, crossover, and mutation.
+fn breed(
+    evolution: &Evolution,
+    ranges: &[Range<f64>; 4],
+    population: &[Genome],
+    fitness: &[f64],
+    rng: &mut StdRng,
+) -> Vec<Genome> {
+    let mut order = (0..population.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        fitness[b]
+            .partial_cmp(&fitness[a])
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut next = order
+        .iter()
+        .take(evolution.elites)
+        .map(|&index| population[index])
+        .collect::<Vec<_>>();
+
+    while next.len() < population.len() {
+        let mut child = population[tournament(rng, fitness, evolution.tournament)];
+        if rng.gen::<f64>() < evolution.crossover {
+            let other = population[tournament(rng, fitness, evolution.tournament)];
+            for (gene, other) in child.iter_mut().zip(&other) {
+                if rng.gen::<bool>() {
+                    *gene = *other;
+                }
+            }
+        }
+        for (gene, range) in child.iter_mut().zip(ranges) {
+            if range.start < range.end && rng.gen::<f64>() < evolution.mutation_rate {
+                // Box-Muller transform for a normally distributed mutation
+                let normal = (-2. * (1. - rng.gen::<f64>()).ln()).sqrt()
+                    * (2. * std::f64::consts::PI * rng.gen::<f64>()).cos();
+                let width = range.end - range.start;
+                *gene = (*gene + normal * evolution.mutation * width).clamp(range.start, range.end);
+            }
+        }
+        next.push(child);
+    }
+    next
+}
+
+/// Evolves the properties of the players of one combination and returns the statistics of every
+/// generation.
+#[allow(clippy::cast_precision_loss)]
+pub fn evolve(experiment: &Experiment, index: usize, run: &Run) -> Vec<Generation> {
+    let evolution = experiment
+        .evolution
+        .as_ref()
+        .expect("the experiment describes an evolution");
+    let ranges = ranges(&run.config.agents);
+    let mut rng = StdRng::seed_from_u64(experiment.seed);
+    let mut population = create_simulation(&run.config, experiment.seed)
+        .agents()
+        .iter()
+        .map(|(_, state)| genome(&state.properties))
+        .collect::<Vec<_>>();
+    let players = population.len() as f64;
+    let games = experiment.replications;
+
+    let mut generations = Vec::with_capacity(evolution.generations);
+    for generation in 0..evolution.generations {
+        let results = (0..games)
+            .into_par_iter()
+            .map(|game| {
+                let seed = experiment
+                    .seed
+                    .wrapping_add(generation as u64 * games)
+                    .wrapping_add(game);
+                play(experiment, run, &population, seed)
+            })
+            .collect::<Vec<_>>();
+        let fitness = (0..population.len())
+            .map(|player| {
+                results
+                    .iter()
+                    .map(|(fitness, _)| fitness[player])
+                    .sum::<f64>()
+                    / games.max(1) as f64
+            })
+            .collect::<Vec<_>>();
+
+        let mut genes = [(0., 0.); 4];
+        for (index, (mean, std_dev)) in genes.iter_mut().enumerate() {
+            *mean = population.iter().map(|genome| genome[index]).sum::<f64>() / players;
+            let variance = population
+                .iter()
+                .map(|genome| (genome[index] - *mean).powi(2))
+                .sum::<f64>()
+                / players;
+            *std_dev = variance.sqrt();
+        }
+        generations.push(Generation {
+            run: index,
+            generation,
+            mean_fitness: fitness.iter().sum::<f64>() / players,
+            best_fitness: fitness.iter().copied().fold(0., f64::max),
+            mean_tags: results.iter().map(|(_, tags)| *tags as f64).sum::<f64>()
+                / games.max(1) as f64,
+            genes,
+        });
+
+        population = breed(evolution, &ranges, &population, &fitness, &mut rng);
+    }
+    generations
+}
+
+/// Writes the statistics of every generation.
+pub fn write(
+    writer: &mut impl Write,
+    format: Format,
+    experiment: &Experiment,
+    runs: &[Run],
+    generations: &[Generation],
+) -> io::Result<()> {
+    let parameters = experiment.parameter_names();
+    let mut columns = vec![
+        "mean_fitness".to_string(),
+        "best_fitness".to_string(),
+        "mean_tags".to_string(),
+    ];
+    for gene in &GENES {
+        columns.push(format!("{}_mean", gene));
+        columns.push(format!("{}_std", gene));
+    }
+    if format == Format::Csv {
+        let header = Some("run")
+            .into_iter()
+            .chain(parameters.iter().map(|name| name.as_str()))
+            .chain(Some("generation"))
+            .chain(columns.iter().map(String::as_str))
+            .map(csv_field)
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", header.join(","))?;
+    }
+
+    for generation in generations {
+        let mut values = vec![
+            generation.mean_fitness,
+            generation.best_fitness,
+            generation.mean_tags,
+        ];
+        for (mean, std_dev) in &generation.genes {
+            values.push(*mean);
+            values.push(*std_dev);
+        }
+        let parameters = &runs[generation.run].parameters;
+        match format {
+            Format::Csv => {
+                let fields = Some(generation.run.to_string())
+                    .into_iter()
+                    .chain(parameters.iter().map(|(_, value)| csv_value(value)))
+                    .chain(Some(generation.generation.to_string()))
+                    .chain(values.iter().map(ToString::to_string))
+                    .map(|field| csv_field(&field))
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+            Format::Jsonl => {
+                let mut object = Map::new();
+                object.insert("run".to_string(), generation.run.into());
+                for (name, value) in parameters {
+                    object.insert(name.clone(), value.clone());
+                }
+                object.insert("generation".to_string(), generation.generation.into());
+                for (name, value) in columns.iter().zip(values) {
+                    object.insert(name.clone(), Value::from(value));
+                }
+                serde_json::to_writer(&mut *writer, &object)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the mean properties of the last generation of every combination.
+pub fn print(runs: &[Run], generations: &[Generation]) {
+    for generation in generations
+        .windows(2)
+        .filter(|pair| pair[0].run != pair[1].run)
+        .map(|pair| &pair[0])
+        .chain(generations.last())
+    {
+        let parameters = runs[generation.run]
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        println!(
+            "Generation {} ({}):",
+            generation.generation,
+            if parameters.is_empty() {
+                "base configuration".to_string()
+            } else {
+                parameters.join(", ")
+            }
+        );
+        for (name, (mean, std_dev)) in GENES.iter().zip(&generation.genes) {
+            println!("  {} = {:.4} ± {:.4}", name, mean, std_dev);
+        }
+    }
+}
@@ -0,0 +1,609 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tag_game::{Design, Method, SensitivityAnalysis, Simulation};
+
+use crate::{
+    agent::{Tag, TagAgent},
+    config::Config,
+};
+
+/// Describes, which games are run and what is measured.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    /// Values overriding the default configuration of the game for all runs.
+    #[serde(default)]
+    pub config: Map<String, Value>,
+    /// The varied parameters. Every combination of values is run.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Variation>,
+    /// Varies the parameters with a sampling design instead of every combination.
+    #[serde(default)]
+    pub sensitivity: Option<Sensitivity>,
+    /// Searches the parameters, which reproduce observed values of the metrics.
+    #[serde(default)]
+    pub calibration: Option<Calibration>,
+    /// Evolves the properties of the players over generations for every combination.
+    #[serde(default)]
+    pub evolution: Option<Evolution>,
+    /// The number of replications of every combination.
+    #[serde(default = "Experiment::default_replications")]
+    pub replications: u64,
+    /// The seed of the first replication.
+    #[serde(default)]
+    pub seed: u64,
+    /// When a run is stopped.
+    pub stop: Stop,
+    /// The metrics to collect.
+    #[serde(default)]
+    pub metrics: Vec<Metric>,
+    /// Measure every `n` ticks. If not set, only the final tick is measured.
+    #[serde(default)]
+    pub measure_every: Option<u64>,
+    /// The directory of the experiment file, in which the map file of the game is looked up.
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+/// The values of a varied parameter.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Variation {
+    /// The values from `first` to `last` (inclusive), increased by `step`.
+    Steps { first: f64, step: f64, last: f64 },
+    /// A list of values.
+    Values(Vec<Value>),
+}
+
+/// The sampling design of a sensitivity analysis.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingDesign {
+    LatinHypercube,
+    Sobol,
+    Morris,
+}
+
+/// Describes a sensitivity analysis of the final values of the metrics.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sensitivity {
+    /// The sampling design.
+    pub design: SamplingDesign,
+    /// The number of samples, or the number of trajectories for Morris' design.
+    pub samples: usize,
+    /// The number of values per factor for Morris' design.
+    #[serde(default = "Sensitivity::default_levels")]
+    pub levels: usize,
+    /// The varied parameters with their ranges.
+    pub factors: BTreeMap<String, Range<f64>>,
+}
+
+impl Sensitivity {
+    const fn default_levels() -> usize {
+        4
+    }
+
+    /// Returns the analysis described by the experiment.
+    pub fn analysis(&self, seed: u64) -> SensitivityAnalysis {
+        let design = match self.design {
+            SamplingDesign::LatinHypercube => Design::LatinHypercube {
+                samples: self.samples,
+            },
+            SamplingDesign::Sobol => Design::Sobol {
+                samples: self.samples,
+            },
+            SamplingDesign::Morris => Design::Morris {
+                trajectories: self.samples,
+                levels: self.levels,
+            },
+        };
+        self.factors
+            .iter()
+            .fold(
+                SensitivityAnalysis::new(design),
+                |analysis, (name, range)| analysis.factor(name.clone(), range.clone()),
+            )
+            .seed(seed)
+    }
+}
+
+/// The search method of a calibration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SearchMethod {
+    Rejection {
+        samples: usize,
+        accepted: usize,
+    },
+    Evolution {
+        population: usize,
+        generations: usize,
+    },
+}
+
+/// Describes a calibration of parameters to observed final values of the metrics.
+#[derive(Debug, Deserialize)]
+pub struct Calibration {
+    /// The search method with its settings.
+    #[serde(flatten)]
+    pub method: SearchMethod,
+    /// The searched parameters with their ranges.
+    pub factors: BTreeMap<String, Range<f64>>,
+    /// The observed values of the metrics.
+    pub targets: BTreeMap<Metric, f64>,
+}
+
+impl Calibration {
+    /// Returns the calibration described by the experiment.
+    pub fn calibration(&self, seed: u64) -> tag_game::Calibration {
+        let method = match self.method {
+            SearchMethod::Rejection { samples, accepted } => {
+                Method::Rejection { samples, accepted }
+            }
+            SearchMethod::Evolution {
+                population,
+                generations,
+            } => Method::Evolution {
+                population,
+                generations,
+            },
+        };
+        let calibration = self.factors.iter().fold(
+            tag_game::Calibration::new(method),
+            |calibration, (name, range)| calibration.factor(name.clone(), range.clone()),
+        );
+        self.targets
+            .iter()
+            .fold(calibration, |calibration, (metric, value)| {
+                calibration.target(metric.name(), *value)
+            })
+            .seed(seed)
+    }
+}
+
+/// Describes the genetic algorithm, which evolves the properties of the players.
+///
+/// Every generation plays one game per replication. The properties are kept within the ranges of
+/// the configuration.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Evolution {
+    /// The number of generations.
+    pub generations: usize,
+    /// The number of players competing for every parent.
+    #[serde(default = "Evolution::default_tournament")]
+    pub tournament: usize,
+    /// The probability, that a child has two parents.
+    #[serde(default = "Evolution::default_crossover")]
+    pub crossover: f64,
+    /// The probability, that a property of a child is mutated.
+    #[serde(default = "Evolution::default_mutation_rate")]
+    pub mutation_rate: f64,
+    /// The standard deviation of a mutation relative to the range of the property.
+    #[serde(default = "Evolution::default_mutation")]
+    pub mutation: f64,
+    /// The number of the fittest players, which are kept unchanged.
+    #[serde(default = "Evolution::default_elites")]
+    pub elites: usize,
+}
+
+impl Evolution {
+    const fn default_tournament() -> usize {
+        3
+    }
+
+    const fn default_crossover() -> f64 {
+        0.9
+    }
+
+    const fn default_mutation_rate() -> f64 {
+        0.2
+    }
+
+    const fn default_mutation() -> f64 {
+        0.1
+    }
+
+    const fn default_elites() -> usize {
+        1
+    }
+}
+
+/// The conditions to stop a run. A run stops, as soon as one of them is met.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stop {
+    /// The maximum number of ticks.
+    pub ticks: u64,
+    /// The number of tags.
+    #[serde(default)]
+    pub tags: Option<u64>,
+}
+
+impl Stop {
+    /// Returns if the game tracked by `tracker` is over.
+    pub fn reached(&self, simulation: &Simulation<TagAgent>, tracker: &Tracker) -> bool {
+        simulation.tick() >= self.ticks
+            || self.tags.is_some_and(|tags| tracker.tags() >= tags)
+            || simulation.world().finished
+    }
+}
+
+/// An output of a run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// The number of tags.
+    Tags,
+    /// The number of players, which have been "It" at least once.
+    DistinctIts,
+    /// The mean number of ticks "It" needed to tag someone.
+    MeanChaseTicks,
+    /// The longest number of ticks, a player was "It", including the current one.
+    LongestChaseTicks,
+    /// The mean distance of all other players to the nearest "It".
+    MeanDistanceToIt,
+    /// The number of frozen players in freeze tag.
+    Frozen,
+    /// The number of jailed players in capture the flag.
+    Jailed,
+    /// The number of flags carried home in capture the flag.
+    Captures,
+    /// The number of found players in hide-and-seek.
+    Found,
+    /// The number of "It"s.
+    Its,
+}
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tags => "tags",
+            Self::DistinctIts => "distinct_its",
+            Self::MeanChaseTicks => "mean_chase_ticks",
+            Self::LongestChaseTicks => "longest_chase_ticks",
+            Self::MeanDistanceToIt => "mean_distance_to_it",
+            Self::Frozen => "frozen",
+            Self::Jailed => "jailed",
+            Self::Captures => "captures",
+            Self::Found => "found",
+            Self::Its => "its",
+        }
+    }
+}
+
+/// One combination of parameter values.
+pub struct Run {
+    /// The varied parameters with their values.
+    pub parameters: Vec<(String, Value)>,
+    /// The configuration of the game.
+    pub config: Config,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes `value` into the field at `path` of `target`.
+///
+/// `path` consists of field names separated by dots. A number written to a range sets both ends,
+/// so every player gets the same value.
+fn set(target: &mut Value, path: &str, value: Value) -> io::Result<()> {
+    let mut field = target;
+    for name in path.split('.') {
+        field = field
+            .get_mut(name)
+            .ok_or_else(|| invalid(format!("unknown parameter `{}`", path)))?;
+    }
+    match field {
+        Value::Object(range) if value.is_number() && range.contains_key("start") => {
+            range.insert("start".to_string(), value.clone());
+            range.insert("end".to_string(), value);
+        }
+        Value::Object(_) if value.is_object() => {
+            if let Value::Object(values) = value {
+                for (name, value) in values {
+                    set(field, &name, value)?;
+                }
+            }
+        }
+        _ => *field = value,
+    }
+    Ok(())
+}
+
+impl Variation {
+    /// Returns all values of the parameter.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn values(&self) -> Vec<Value> {
+        match self {
+            Self::Values(values) => values.clone(),
+            Self::Steps { first, step, last } => {
+                let count = ((last - first) / step + 1e-9).floor().max(-1.) as usize + 1;
+                (0..count)
+                    // Round away errors like `0.9 + 3. * 0.1 = 1.2000000000000002`
+                    .map(|index| ((first + index as f64 * step) * 1e9).round() / 1e9)
+                    .map(|value| {
+                        // Integral values are written as integers, so they can be used for counts
+                        if value.fract() == 0. && value.abs() < 2_f64.powi(53) {
+                            Value::from(value as i64)
+                        } else {
+                            Value::from(value)
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Experiment {
+    const fn default_replications() -> u64 {
+        1
+    }
+
+    /// Reads the experiment from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(&path)?;
+        let mut experiment: Self = serde_json::from_reader(BufReader::new(file))?;
+        if let Some(directory) = path.as_ref().parent() {
+            experiment.directory = directory.to_path_buf();
+        }
+        if experiment.measure_every == Some(0) {
+            return Err(invalid("`measure_every` must be positive".to_string()));
+        }
+        let modes = [
+            !experiment.parameters.is_empty() || experiment.evolution.is_some(),
+            experiment.sensitivity.is_some(),
+            experiment.calibration.is_some(),
+        ];
+        if modes.iter().filter(|&&mode| mode).count() > 1 {
+            return Err(invalid(
+                "only one of `parameters` or `evolution`, `sensitivity` and `calibration` can be used"
+                    .to_string(),
+            ));
+        }
+        if experiment
+            .evolution
+            .as_ref()
+            .is_some_and(|evolution| evolution.tournament == 0)
+        {
+            return Err(invalid("`tournament` must be positive".to_string()));
+        }
+        let factors = experiment
+            .sensitivity
+            .iter()
+            .flat_map(|sensitivity| sensitivity.factors.values())
+            .chain(
+                experiment
+                    .calibration
+                    .iter()
+                    .flat_map(|calibration| calibration.factors.values()),
+            );
+        if factors.clone().any(|range| range.start >= range.end) {
+            return Err(invalid(
+                "the range of a factor must not be empty".to_string(),
+            ));
+        }
+        Ok(experiment)
+    }
+
+    /// Returns the names of the varied parameters.
+    pub fn parameter_names(&self) -> Vec<&String> {
+        if let Some(sensitivity) = &self.sensitivity {
+            sensitivity.factors.keys().collect()
+        } else if let Some(calibration) = &self.calibration {
+            calibration.factors.keys().collect()
+        } else {
+            self.parameters.keys().collect()
+        }
+    }
+
+    /// Returns the configuration of the game with the varied parameters set to `parameters`.
+    pub fn config(&self, parameters: &[(String, Value)]) -> io::Result<Config> {
+        let mut config = serde_json::to_value(Config::default())?;
+        for (name, value) in &self.config {
+            set(&mut config, name, value.clone())?;
+        }
+        for (name, value) in parameters {
+            set(&mut config, name, value.clone())?;
+        }
+        let mut config: Config = serde_json::from_value(config)
+            .map_err(|error| invalid(format!("invalid parameters: {}", error)))?;
+        config.load_map(&self.directory)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns every combination of parameter values with its configuration.
+    ///
+    /// For a sensitivity analysis, the combinations are the points of the sampling design.
+    pub fn runs(&self) -> io::Result<Vec<Run>> {
+        let mut combinations = vec![Vec::new()];
+        if let Some(sensitivity) = &self.sensitivity {
+            let names = sensitivity.factors.keys();
+            combinations = sensitivity
+                .analysis(self.seed)
+                .points()
+                .into_iter()
+                .map(|point| {
+                    names
+                        .clone()
+                        .cloned()
+                        .zip(point.into_iter().map(Value::from))
+                })
+                .map(Iterator::collect)
+                .collect();
+        }
+        for (name, variation) in &self.parameters {
+            let values = variation.values();
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination: Vec<(String, Value)>| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((name.clone(), value.clone()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        combinations
+            .into_iter()
+            .map(|parameters| {
+                let config = self.config(&parameters)?;
+                Ok(Run { parameters, config })
+            })
+            .collect()
+    }
+}
+
+/// Follows a game to compute the metrics, which depend on previous ticks.
+pub struct Tracker {
+    /// The agent id and the tick of the latest tag for every "It"
+    its: Vec<(usize, u64)>,
+    tags: u64,
+    chase_ticks: u64,
+    longest_chase: u64,
+    been_it: Vec<bool>,
+    ticks_as_it: Vec<u64>,
+    caught: Vec<bool>,
+}
+
+impl Tracker {
+    pub fn new(simulation: &Simulation<TagAgent>) -> Self {
+        let mut been_it = vec![false; simulation.agents().len()];
+        let its = simulation
+            .world()
+            .its
+            .iter()
+            .map(|it| {
+                been_it[it.id] = true;
+                (it.id, simulation.tick())
+            })
+            .collect();
+        Self {
+            its,
+            tags: 0,
+            chase_ticks: 0,
+            longest_chase: 0,
+            ticks_as_it: vec![0; been_it.len()],
+            caught: vec![false; been_it.len()],
+            been_it,
+        }
+    }
+
+    pub fn tags(&self) -> u64 {
+        self.tags
+    }
+
+    /// Returns the number of ticks every player has been "It", including the current "It"s.
+    pub fn ticks_as_it(&self, simulation: &Simulation<TagAgent>) -> Vec<u64> {
+        let mut ticks = self.ticks_as_it.clone();
+        for &(id, last_tag) in &self.its {
+            ticks[id] += simulation.tick() - last_tag;
+        }
+        ticks
+    }
+
+    /// Checks for tags after every tick.
+    pub fn observe(&mut self, simulation: &Simulation<TagAgent>) {
+        // In infection tag, every new "It" is a tag
+        for it in &simulation.world().its[self.its.len()..] {
+            self.tags += 1;
+            self.its.push((it.id, simulation.tick()));
+            self.been_it[it.id] = true;
+        }
+        for (it, (id, last_tag)) in simulation.world().its.iter().zip(&mut self.its) {
+            if it.id != *id {
+                let chase = simulation.tick() - *last_tag;
+                self.tags += 1;
+                self.chase_ticks += chase;
+                self.longest_chase = self.longest_chase.max(chase);
+                self.ticks_as_it[*id] += chase;
+                *id = it.id;
+                *last_tag = simulation.tick();
+                self.been_it[it.id] = true;
+            }
+        }
+        // In freeze tag, capture the flag and hide-and-seek, every newly frozen, jailed or found
+        // player is a tag
+        for (caught, (_, state)) in self.caught.iter_mut().zip(simulation.agents()) {
+            let now = matches!(
+                state.tag,
+                Tag::Frozen { .. } | Tag::Jailed { .. } | Tag::Found
+            );
+            if now && !*caught {
+                self.tags += 1;
+            }
+            *caught = now;
+        }
+    }
+
+    /// Returns the value of `metric` at the current tick, if it's defined.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn measure(&self, metric: Metric, simulation: &Simulation<TagAgent>) -> Option<f64> {
+        let count = |filter: fn(Tag) -> bool| {
+            simulation
+                .agents()
+                .iter()
+                .filter(|(_, state)| filter(state.tag))
+                .count() as f64
+        };
+        match metric {
+            Metric::Tags => Some(self.tags as f64),
+            Metric::DistinctIts => Some(self.been_it.iter().filter(|&&it| it).count() as f64),
+            Metric::MeanChaseTicks if self.tags == 0 => None,
+            Metric::MeanChaseTicks => Some(self.chase_ticks as f64 / self.tags as f64),
+            Metric::LongestChaseTicks => {
+                let current = self
+                    .its
+                    .iter()
+                    .map(|(_, last_tag)| simulation.tick() - last_tag)
+                    .max()
+                    .unwrap_or(0);
+                Some(self.longest_chase.max(current) as f64)
+            }
+            Metric::MeanDistanceToIt if self.its.is_empty() => None,
+            Metric::MeanDistanceToIt => {
+                let agents = simulation.agents();
+                let others = agents
+                    .iter()
+                    .filter(|(_, state)| !matches!(state.tag, Tag::It(_)))
+                    .map(|(_, state)| {
+                        let nearest = self
+                            .its
+                            .iter()
+                            .map(|&(id, _)| state.position.distance_squared(agents[id].1.position))
+                            .fold(f32::INFINITY, f32::min);
+                        f64::from(nearest.sqrt())
+                    })
+                    .collect::<Vec<_>>();
+                if others.is_empty() {
+                    None
+                } else {
+                    Some(others.iter().sum::<f64>() / others.len() as f64)
+                }
+            }
+            Metric::Frozen => Some(count(|tag| matches!(tag, Tag::Frozen { .. }))),
+            Metric::Jailed => Some(count(|tag| matches!(tag, Tag::Jailed { .. }))),
+            Metric::Found => Some(count(|tag| tag == Tag::Found)),
+            Metric::Captures => Some(simulation.world().scores.iter().sum::<u32>() as f64),
+            Metric::Its => Some(simulation.world().its.len() as f64),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+#![allow(clippy::module_name_repetitions, clippy::cast_lossless)]
+
+#[allow(dead_code)]
+#[path = "../tag/agent.rs"]
+mod agent;
+mod calibration;
+#[allow(dead_code)]
+#[path = "../tag/config.rs"]
+mod config;
+mod evolution;
+mod experiment;
+#[allow(dead_code)]
+#[path = "../tag/map.rs"]
+mod map;
+mod sensitivity;
+#[allow(dead_code)]
+#[path = "../tag/world.rs"]
+mod world;
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use rayon::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::{
+    config::Config,
+    experiment::{Experiment, Metric, Run, Tracker},
+    world::{create_simulation, Board},
+};
+
+/// The format of the results file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// One line per measurement with a header line.
+    Csv,
+    /// One JSON object per measurement.
+    Jsonl,
+}
+
+/// The measured values of a replication at one tick.
+pub struct Row {
+    run: usize,
+    replication: u64,
+    seed: u64,
+    tick: u64,
+    values: Vec<Option<f64>>,
+}
+
+/// Plays one replication of a run headless and returns the measurements of `metrics`.
+fn replicate(
+    experiment: &Experiment,
+    metrics: &[Metric],
+    run: usize,
+    config: &Config,
+    replication: u64,
+) -> Vec<Row> {
+    // Every run uses the same seeds, so different parameters are compared on the same games
+    let seed = experiment.seed.wrapping_add(replication);
+    let mut simulation = create_simulation(config, seed);
+    // Nothing is rewound in an experiment
+    simulation.set_history_capacity(0);
+    let mut tracker = Tracker::new(&simulation);
+    let mut rows = Vec::new();
+    loop {
+        let stopped = experiment.stop.reached(&simulation, &tracker);
+        let due = experiment
+            .measure_every
+            .is_some_and(|every| simulation.tick().is_multiple_of(every));
+        if due || stopped {
+            rows.push(Row {
+                run,
+                replication,
+                seed,
+                tick: simulation.tick(),
+                values: metrics
+                    .iter()
+                    .map(|&metric| tracker.measure(metric, &simulation))
+                    .collect(),
+            });
+        }
+        if stopped {
+            return rows;
+        }
+        simulation.update();
+        tracker.observe(&simulation);
+    }
+}
+
+/// Quotes a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Formats a parameter value for a CSV field.
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn write_results(
+    writer: &mut impl Write,
+    format: Format,
+    experiment: &Experiment,
+    runs: &[Run],
+    rows: &[&Row],
+) -> io::Result<()> {
+    let parameters = experiment.parameter_names();
+    if format == Format::Csv {
+        let header = ["run", "replication", "seed"]
+            .iter()
+            .copied()
+            .chain(parameters.iter().map(|name| name.as_str()))
+            .chain(Some("tick"))
+            .chain(experiment.metrics.iter().map(|metric| metric.name()))
+            .map(csv_field)
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", header.join(","))?;
+    }
+
+    for row in rows {
+        let values = &runs[row.run].parameters;
+        match format {
+            Format::Csv => {
+                let fields = [row.run as u64, row.replication, row.seed]
+                    .iter()
+                    .map(ToString::to_string)
+                    .chain(values.iter().map(|(_, value)| csv_value(value)))
+                    .chain(Some(row.tick.to_string()))
+                    .chain(
+                        row.values
+                            .iter()
+                            .map(|value| value.map_or_else(String::new, |v| v.to_string())),
+                    )
+                    .map(|field| csv_field(&field))
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+            Format::Jsonl => {
+                let mut object = Map::new();
+                object.insert("run".to_string(), row.run.into());
+                object.insert("replication".to_string(), row.replication.into());
+                object.insert("seed".to_string(), row.seed.into());
+                for (name, value) in values {
+                    object.insert(name.clone(), value.clone());
+                }
+                object.insert("tick".to_string(), row.tick.into());
+                for (metric, value) in experiment.metrics.iter().zip(&row.values) {
+                    object.insert(metric.name().to_string(), (*value).into());
+                }
+                serde_json::to_writer(&mut *writer, &object)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), io::Error> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+    };
+    let usage = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: tag-experiment <experiment file> [--output <directory>] [--format csv|jsonl]",
+        )
+    };
+
+    let path = args.first().filter(|arg| !arg.starts_with("--"));
+    let experiment = Experiment::load(path.ok_or_else(usage)?)?;
+    let output = option("--output").map_or_else(|| PathBuf::from("results"), PathBuf::from);
+    let format = match option("--format").map(String::as_str) {
+        None | Some("csv") => Format::Csv,
+        Some("jsonl") => Format::Jsonl,
+        Some(_) => return Err(usage()),
+    };
+    let extension = match format {
+        Format::Csv => "csv",
+        Format::Jsonl => "jsonl",
+    };
+    fs::create_dir_all(&output)?;
+
+    if experiment.calibration.is_some() {
+        return calibration::run(&experiment, &output, format, extension);
+    }
+
+    let runs = experiment.runs()?;
+    if experiment.evolution.is_some() {
+        eprintln!(
+            "Evolving {} combinations with {} games per generation",
+            runs.len(),
+            experiment.replications
+        );
+        let start = Instant::now();
+        let generations = runs
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(index, run)| evolution::evolve(&experiment, index, run))
+            .collect::<Vec<_>>();
+        let path = output.join(format!("evolution.{}", extension));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        evolution::write(&mut writer, format, &experiment, &runs, &generations)?;
+        writer.flush()?;
+        evolution::print(&runs, &generations);
+        eprintln!(
+            "Wrote {} generations to {} in {:.2?}",
+            generations.len(),
+            path.display(),
+            start.elapsed()
+        );
+        return Ok(());
+    }
+
+    let jobs = (0..runs.len())
+        .flat_map(|run| (0..experiment.replications).map(move |replication| (run, replication)))
+        .collect::<Vec<_>>();
+    eprintln!(
+        "Running {} combinations with {} replications each",
+        runs.len(),
+        experiment.replications
+    );
+
+    let start = Instant::now();
+    let replications = jobs
+        .into_par_iter()
+        .map(|(run, replication)| {
+            let config = &runs[run].config;
+            replicate(&experiment, &experiment.metrics, run, config, replication)
+        })
+        .collect::<Vec<_>>();
+    let rows = replications.iter().flatten().collect::<Vec<_>>();
+
+    let path = output.join(format!("results.{}", extension));
+    let mut writer = BufWriter::new(File::create(&path)?);
+    write_results(&mut writer, format, &experiment, &runs, &rows)?;
+    writer.flush()?;
+    eprintln!(
+        "Wrote {} measurements to {} in {:.2?}",
+        rows.len(),
+        path.display(),
+        start.elapsed()
+    );
+
+    if experiment.sensitivity.is_some() {
+        let rankings = sensitivity::analyze(&experiment, &replications);
+        let path = output.join(format!("sensitivity.{}", extension));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        sensitivity::write(&mut writer, format, &rankings)?;
+        writer.flush()?;
+        sensitivity::print(&rankings);
+        eprintln!("Wrote the sensitivity indices to {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+
+use serde_json::{Map, Value};
+use tag_game::{FactorSensitivity, Indices};
+
+use crate::{experiment::Experiment, Format, Row};
+
+/// The ranked factors of a metric.
+pub struct Ranking {
+    pub metric: &'static str,
+    pub factors: Vec<FactorSensitivity>,
+}
+
+/// Returns the names and values of the indices.
+fn fields(indices: Indices) -> Vec<(&'static str, f64)> {
+    match indices {
+        Indices::RankCorrelation(correlation) => vec![("rank_correlation", correlation)],
+        Indices::Sobol { first_order, total } => {
+            vec![("first_order", first_order), ("total", total)]
+        }
+        Indices::Morris { mu, mu_star, sigma } => {
+            vec![("mu", mu), ("mu_star", mu_star), ("sigma", sigma)]
+        }
+    }
+}
+
+/// Analyzes the mean final value of every metric over the replications of every run.
+///
+/// Metrics, which are not defined in a run, like the mean chase without any tag, are skipped.
+#[allow(clippy::cast_precision_loss)]
+pub fn analyze(experiment: &Experiment, replications: &[Vec<Row>]) -> Vec<Ranking> {
+    let sensitivity = match &experiment.sensitivity {
+        Some(sensitivity) => sensitivity,
+        None => return Vec::new(),
+    };
+    let analysis = sensitivity.analysis(experiment.seed);
+    let runs = replications.chunks(experiment.replications as usize);
+
+    let mut rankings = Vec::new();
+    for (index, metric) in experiment.metrics.iter().enumerate() {
+        let outputs = runs
+            .clone()
+            .map(|replications| {
+                let values = replications
+                    .iter()
+                    .filter_map(|rows| rows.last()?.values[index])
+                    .collect::<Vec<_>>();
+                if values.is_empty() {
+                    None
+                } else {
+                    Some(values.iter().sum::<f64>() / values.len() as f64)
+                }
+            })
+            .collect::<Option<Vec<_>>>();
+        match outputs {
+            Some(outputs) => rankings.push(Ranking {
+                metric: metric.name(),
+                factors: analysis.analyze(&outputs),
+            }),
+            None => eprintln!(
+                "Skipping `{}` in the sensitivity analysis, as it's undefined in some runs",
+                metric.name()
+            ),
+        }
+    }
+    rankings
+}
+
+/// Prints the factors of every metric from the most to the least influential one.
+pub fn print(rankings: &[Ranking]) {
+    for ranking in rankings {
+        println!("{}:", ranking.metric);
+        for (rank, factor) in ranking.factors.iter().enumerate() {
+            let indices = fields(factor.indices)
+                .iter()
+                .map(|(name, value)| format!("{} = {:.4}", name, value))
+                .collect::<Vec<_>>();
+            println!("  {}. {} ({})", rank + 1, factor.factor, indices.join(", "));
+        }
+    }
+}
+
+pub fn write(writer: &mut impl Write, format: Format, rankings: &[Ranking]) -> io::Result<()> {
+    let mut header = true;
+    for ranking in rankings {
+        for (rank, factor) in ranking.factors.iter().enumerate() {
+            let indices = fields(factor.indices);
+            match format {
+                Format::Csv => {
+                    if header {
+                        let names = indices.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                        writeln!(writer, "metric,rank,factor,{}", names.join(","))?;
+                        header = false;
+                    }
+                    let values = indices
+                        .iter()
+                        .map(|(_, value)| value.to_string())
+                        .collect::<Vec<_>>();
+                    writeln!(
+                        writer,
+                        "{},{},{},{}",
+                        ranking.metric,
+                        rank + 1,
+                        factor.factor,
+                        values.join(",")
+                    )?;
+                }
+                Format::Jsonl => {
+                    let mut object = Map::new();
+                    object.insert("metric".to_string(), ranking.metric.into());
+                    object.insert("rank".to_string(), (rank + 1).into());
+                    object.insert("factor".to_string(), factor.factor.clone().into());
+                    for (name, value) in indices {
+                        object.insert(name.to_string(), Value::from(value));
+                    }
+                    serde_json::to_writer(&mut *writer, &object)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,15 @@
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, Rng};
 use serde::{Deserialize, Serialize};
 
 use tag_game::Agent;
 
-use crate::world::{Board, TagWorld};
+use crate::{
+    config::Mode,
+    world::{Board, TagWorld},
+};
+
+/// The distance, in which "It" is able to tag another agent.
+pub const TAG_RADIUS: f32 = 1.75;
 
 /// The state, if an agent is tagged.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -11,10 +17,43 @@ pub enum Tag {
     /// The agent is currently "It". If the id is set, "It" has tagged
     /// a new agent, which will become "It" next tick.
     It(Option<usize>),
-    /// The agent recently was "It".
-    Recent,
     /// The agent can be tagged by "It".
     None,
+    /// The agent was tagged in freeze tag and can't move. If `rescued` is set, another agent
+    /// touched the frozen agent, which will be unfrozen next tick.
+    Frozen { rescued: bool },
+    /// The agent was caught in the territory of the other team in capture the flag and waits in
+    /// their jail. If `rescued` is set, a team mate touched the agent in the jail, which will be
+    /// released to the flag of its team next tick.
+    Jailed { rescued: bool },
+    /// The agent was found in hide-and-seek and is out of the game.
+    Found,
+}
+
+/// A team in capture the flag.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    /// Returns the other team.
+    pub fn opponent(self) -> Self {
+        match self {
+            Self::Red => Self::Blue,
+            Self::Blue => Self::Red,
+        }
+    }
+
+    /// Returns the index of the team, starting with `0`.
+    pub fn index(self) -> usize {
+        match self {
+            Self::Red => 0,
+            Self::Blue => 1,
+        }
+    }
 }
 
 /// Simple abstraction over a position for an agent
@@ -50,6 +89,8 @@ pub struct Properties {
 #[derive(Clone, PartialEq, Debug)]
 pub struct AgentState {
     pub tag: Tag,
+    /// The team of the agent in capture the flag.
+    pub team: Option<Team>,
     pub position: Position,
     pub properties: Properties,
 }
@@ -58,15 +99,198 @@ pub struct AgentState {
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct TagAgent;
 
+/// Returns the direction from `from` towards `to`.
+fn towards(from: Position, to: Position) -> (f32, f32) {
+    (
+        if to.x > from.x { 1. } else { -1. },
+        if to.y > from.y { 1. } else { -1. },
+    )
+}
+
+/// Returns the direction from `from` away from `to`.
+fn away(from: Position, to: Position) -> (f32, f32) {
+    (
+        if to.x < from.x { 1. } else { -1. },
+        if to.y < from.y { 1. } else { -1. },
+    )
+}
+
+/// Returns the direction from `from` away from `to`, which leads to the fastest terrain. Besides
+/// running straight away, the agent may turn to either side, as long as it doesn't come closer.
+fn escape(board: &Board, from: Position, to: Position) -> (f32, f32) {
+    let (dx, dy) = away(from, to);
+    let distance = from.distance_squared(to);
+    let speed = |(dx, dy): (f32, f32)| {
+        board
+            .terrain_at(Position::new(from.x + dx, from.y + dy))
+            .speed()
+    };
+    [(dx, -dy), (-dx, dy)]
+        .iter()
+        .copied()
+        .filter(|&(dx, dy)| {
+            Position::new(from.x + dx, from.y + dy).distance_squared(to) >= distance
+        })
+        .fold((dx, dy), |best, direction| {
+            if speed(direction) > speed(best) {
+                direction
+            } else {
+                best
+            }
+        })
+}
+
+/// Returns `true` with the given probability.
+///
+/// chosen by fair dice roll.
+/// guaranteed to be random.
+fn chance(rng: &mut StdRng, probability: f64) -> bool {
+    probability > rng.gen_range(0.0..1.0)
+}
+
+/// Follows `direction` on every axis with the probability `deciding`, otherwise the agent runs
+/// the wrong way.
+fn decide(rng: &mut StdRng, (dx, dy): (f32, f32), deciding: f64, speed: f32) -> (f32, f32) {
+    let dx = dx * (if chance(rng, deciding) { 1. } else { -1. } * speed);
+    let dy = dy * (if chance(rng, deciding) { 1. } else { -1. } * speed);
+    (dx, dy)
+}
+
+/// Moves the agent on the board with the speed of the terrain, it stands on. If an obstacle is
+/// in the way, the agent slides along it or stays, where it is. Agents can't jump over thin
+/// walls, as the whole step must be free.
+fn run(state: &mut AgentState, board: &Board, (dx, dy): (f32, f32)) {
+    let speed = board.terrain_at(state.position).speed();
+    let (dx, dy) = (dx * speed, dy * speed);
+    for (dx, dy) in [(dx, dy), (dx, 0.), (0., dy)] {
+        let position = Position {
+            x: (state.position.x + dx).clamp(0., board.width as f32 - 1.),
+            y: (state.position.y + dy).clamp(0., board.height as f32 - 1.),
+        };
+        if !board.is_blocked(position) && board.line_of_sight(state.position, position) {
+            state.position = position;
+            return;
+        }
+    }
+}
+
+/// Returns the position and the distance of the agent nearest to `position`.
+fn nearest<'a>(
+    position: Position,
+    agents: impl Iterator<Item = &'a AgentState>,
+) -> Option<(Position, f32)> {
+    agents
+        .map(|agent| (agent.position, position.distance_squared(agent.position)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+impl TagAgent {
+    /// Plays capture the flag: carry the flag home, flee from opponents in their territory,
+    /// chase intruders in the own territory, rescue jailed team mates, or steal the flag.
+    fn capture_the_flag(
+        id: usize,
+        state: &AgentState,
+        world: &TagWorld,
+        population: &[(Self, AgentState)],
+        rng: &mut StdRng,
+    ) -> (f32, f32) {
+        let team = state
+            .team
+            .expect("every player has a team in capture the flag");
+        let home = world.board.territory(team);
+        let opponents = || {
+            population
+                .iter()
+                .map(|(_, agent)| agent)
+                .filter(move |agent| agent.team == Some(team.opponent()) && agent.tag == Tag::None)
+        };
+        let properties = &state.properties;
+        let untagged = |direction| {
+            (
+                direction,
+                properties.untagged_deciding,
+                properties.untagged_speed_multiplied,
+            )
+        };
+        // Defenders and attackers alternate within the team. If every attacker is jailed, the
+        // defenders attack as well.
+        let attacker = |id: usize| id / 2 % 2 == 1;
+        let defender = !attacker(id)
+            && population.iter().enumerate().any(|(other, (_, agent))| {
+                attacker(other) && agent.team == Some(team) && agent.tag == Tag::None
+            });
+        let own_flag = world.board.flag(team);
+        // Run around the obstacles on the shortest way
+        let to = |target| towards(state.position, world.waypoint(state.position, target));
+
+        let (direction, deciding, speed) =
+            if world.flags[team.opponent().index()].carrier == Some(id) {
+                untagged(to(own_flag))
+            } else if let Some((opponent, _)) = nearest(state.position, opponents())
+                .filter(|&(_, distance)| !home.contains(state.position) && distance < 25.)
+            {
+                // Flee back home
+                let (dx, _) = towards(state.position, own_flag);
+                let (_, dy) = away(state.position, opponent);
+                untagged((dx, dy))
+            } else if let Some((intruder, _)) = nearest(
+                state.position,
+                opponents().filter(|agent| home.contains(agent.position)),
+            )
+            .filter(|&(_, distance)| defender || distance < 100.)
+            {
+                (
+                    to(intruder),
+                    properties.tagged_deciding,
+                    properties.tagged_speed_multiplied,
+                )
+            } else if defender {
+                untagged(to(own_flag))
+            } else if population.iter().any(|(_, agent)| {
+                agent.team == Some(team) && matches!(agent.tag, Tag::Jailed { .. })
+            }) {
+                untagged(to(world.board.jail(team.opponent()).center()))
+            } else if world.flags[team.opponent().index()].carrier.is_none() {
+                untagged(to(world.board.flag(team.opponent())))
+            } else {
+                untagged(to(own_flag))
+            };
+        decide(rng, direction, deciding, speed)
+    }
+
+    /// Plays the seeker in hide-and-seek: chase the nearest hider in sight, or search the area,
+    /// which was not seen for the longest time.
+    fn seek(
+        state: &AgentState,
+        world: &TagWorld,
+        population: &[(Self, AgentState)],
+        rng: &mut StdRng,
+    ) -> (f32, f32) {
+        let hider = nearest(
+            state.position,
+            population
+                .iter()
+                .map(|(_, agent)| agent)
+                .filter(|agent| agent.tag == Tag::None)
+                .filter(|agent| world.can_see(state.position, agent.position)),
+        );
+        let target = hider
+            .map(|(hider, _)| hider)
+            .or_else(|| world.search.target(state.position))
+            .unwrap_or(state.position);
+        decide(
+            rng,
+            towards(state.position, world.waypoint(state.position, target)),
+            state.properties.tagged_deciding,
+            state.properties.tagged_speed_multiplied,
+        )
+    }
+}
+
 impl Agent for TagAgent {
     type State = AgentState;
     type World = TagWorld;
 
-    #[allow(
-        clippy::cast_possible_truncation,
-        clippy::cast_sign_loss,
-        clippy::cast_lossless
-    )]
     fn on_update(
         &self,
         id: usize,
@@ -74,101 +298,226 @@ impl Agent for TagAgent {
         world: &Self::World,
         population: &[(Self, Self::State)],
     ) {
-        fn run(state: &mut AgentState, board: Board, dx: f32, dy: f32) {
-            state.position.x = (state.position.x + dx).clamp(0., board.width as f32 - 1.);
-            state.position.y = (state.position.y + dy).clamp(0., board.height as f32 - 1.);
+        // Nobody moves after the game has ended
+        if world.finished {
+            return;
         }
 
-        let mut rng = thread_rng();
+        let mut rng = world.rng(id);
 
-        // chosen by fair dice roll.
-        // guaranteed to be random.
-        let mut random_bool = |probability| -> bool { probability > rng.gen_range(0.0..1.0) };
-
-        if world.current_it == id {
+        if world.it(id).is_some() {
             state.tag = Tag::It(None);
-        } else if let Some(recent_it) = world.recent_it {
-            if recent_it == id {
-                state.tag = Tag::Recent;
-            } else {
-                state.tag = Tag::None;
-            }
+        } else if !matches!(
+            state.tag,
+            Tag::Frozen { .. } | Tag::Jailed { .. } | Tag::Found
+        ) {
+            state.tag = Tag::None;
         }
 
         match &mut state.tag {
+            // The seekers wait, until they finished counting
+            Tag::It(_) if world.counting() => {}
+            Tag::It(_) if world.mode == Mode::HideAndSeek => {
+                let step = Self::seek(state, world, population, &mut rng);
+                run(state, &world.board, step);
+            }
             // Search an agent to tag
-            Tag::It(next) => {
+            Tag::It(_) => {
                 let mut nearest = (id, f32::MAX);
-                // Find the nearest agent
-                for (ag_id, (_, agent)) in population.iter().enumerate() {
-                    if id == ag_id {
-                        continue;
-                    }
-                    if let Some(recent_it) = world.recent_it {
-                        if ag_id == recent_it {
+                // Chase the assigned target or find the nearest agent, which can be tagged
+                if let Some(target) = world.it(id).and_then(|it| it.target) {
+                    nearest.0 = target;
+                } else {
+                    for (ag_id, (_, agent)) in population.iter().enumerate() {
+                        if id == ag_id || agent.tag != Tag::None || world.is_recent(id, ag_id) {
                             continue;
                         }
-                    }
-                    let d = state.position.distance_squared(agent.position);
-                    if d < nearest.1 {
-                        nearest = (ag_id, d);
+                        let d = state.position.distance_squared(agent.position);
+                        if d < nearest.1 {
+                            nearest = (ag_id, d);
+                        }
                     }
                 }
 
-                // If "It" is close to another agent, tag it
-                if nearest.0 != id && nearest.1 < 3. {
-                    next.replace(nearest.0);
+                let direction = towards(
+                    state.position,
+                    world.waypoint(state.position, population[nearest.0].1.position),
+                );
+                let step = decide(
+                    &mut rng,
+                    direction,
+                    state.properties.tagged_deciding,
+                    state.properties.tagged_speed_multiplied,
+                );
+                run(state, &world.board, step);
+            }
+            // Frozen and jailed agents wait to be rescued, found agents are out
+            Tag::Frozen { .. } | Tag::Jailed { .. } | Tag::Found => {}
+            // Hiders run away from seekers, who see them. Otherwise, they wait in a place, which
+            // is out of the line of sight of all seekers, or run to the nearest one.
+            Tag::None if world.mode == Mode::HideAndSeek => {
+                let seekers = world
+                    .its
+                    .iter()
+                    .map(|it| population[it.id].1.position)
+                    .collect::<Vec<_>>();
+                let seeker = nearest(
+                    state.position,
+                    world
+                        .its
+                        .iter()
+                        .map(|it| &population[it.id].1)
+                        .filter(|seeker| world.can_see(state.position, seeker.position)),
+                );
+                let direction = if let Some((seeker, _)) = seeker {
+                    escape(&world.board, state.position, seeker)
+                } else if world.is_hidden(state.position, &seekers) {
                     return;
-                }
-
-                let Position { x: ag_x, y: ag_y } = population[nearest.0].1.position;
-                let Position { x, y } = state.position;
-
-                let mut dx = if ag_x > x { 1. } else { -1. };
-                let mut dy = if ag_y > y { 1. } else { -1. };
-                dx *= if random_bool(state.properties.tagged_deciding) {
-                    1.
-                } else {
-                    -1.
-                } * state.properties.tagged_speed_multiplied;
-                dy *= if random_bool(state.properties.tagged_deciding) {
-                    1.
+                } else if let Some(place) = world.hiding_place(state.position, &seekers) {
+                    towards(state.position, world.waypoint(state.position, place))
                 } else {
-                    -1.
-                } * state.properties.tagged_speed_multiplied;
-
-                run(state, world.board, dx, dy);
+                    return;
+                };
+                let step = decide(
+                    &mut rng,
+                    direction,
+                    state.properties.untagged_deciding,
+                    state.properties.untagged_speed_multiplied,
+                );
+                run(state, &world.board, step);
             }
-            // Run around randomly
-            Tag::Recent => {
-                let dx = if random_bool(0.5) { 1. } else { -1. };
-                let dy = if random_bool(0.5) { 1. } else { -1. };
-                run(state, world.board, dx, dy);
+            Tag::None if world.mode == Mode::CaptureTheFlag => {
+                let step = Self::capture_the_flag(id, state, world, population, &mut rng);
+                run(state, &world.board, step);
             }
-            // Flee from "It"
+            // Flee from "It" or rescue a frozen agent
             Tag::None => {
-                let Position { x: it_x, y: it_y } = population[world.current_it].1.position;
-                let Position { x, y } = state.position;
+                // Find the nearest "It"
+                let (it, it_distance) = nearest(
+                    state.position,
+                    world.its.iter().map(|it| &population[it.id].1),
+                )
+                .expect("there is at least one \"It\"");
 
-                let mut dx = if it_x < x { 1. } else { -1. };
-                let mut dy = if it_y < y { 1. } else { -1. };
-                dx *= if random_bool(state.properties.untagged_deciding) {
-                    1.
-                } else {
-                    -1.
-                } * state.properties.untagged_speed_multiplied;
-                dy *= if random_bool(state.properties.untagged_deciding) {
-                    1.
-                } else {
-                    -1.
-                } * state.properties.untagged_speed_multiplied;
+                // Run to the nearest frozen agent, if it's closer than "It"
+                let frozen = nearest(
+                    state.position,
+                    population
+                        .iter()
+                        .map(|(_, agent)| agent)
+                        .filter(|agent| matches!(agent.tag, Tag::Frozen { .. })),
+                )
+                .filter(|&(_, distance)| distance < it_distance);
+                if let Some((frozen, _)) = frozen {
+                    let step = decide(
+                        &mut rng,
+                        towards(state.position, world.waypoint(state.position, frozen)),
+                        state.properties.untagged_deciding,
+                        state.properties.untagged_speed_multiplied,
+                    );
+                    run(state, &world.board, step);
+                    return;
+                }
+
+                // Stay in a base as long as it's safe, or run into the nearest one, if it's closer
+                // than "It", who is already close
+                if world.is_safe(id, state.position) {
+                    return;
+                }
+                let base = world
+                    .board
+                    .bases
+                    .iter()
+                    .map(|base| base.center())
+                    .map(|center| (center, state.position.distance_squared(center)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .filter(|&(_, distance)| {
+                        world.dwell[id] < world.base_ticks
+                            && distance < it_distance
+                            && it_distance < 100_f32
+                    });
+                if let Some((base, _)) = base {
+                    let step = decide(
+                        &mut rng,
+                        towards(state.position, world.waypoint(state.position, base)),
+                        state.properties.untagged_deciding,
+                        state.properties.untagged_speed_multiplied,
+                    );
+                    run(state, &world.board, step);
+                    return;
+                }
 
-                if (Position { x, y }).distance_squared(Position { x: it_x, y: it_y }) > 400_f32 {
+                let (mut dx, mut dy) = decide(
+                    &mut rng,
+                    escape(&world.board, state.position, it),
+                    state.properties.untagged_deciding,
+                    state.properties.untagged_speed_multiplied,
+                );
+                // Don't run away too far
+                if it_distance > 400_f32 {
                     dx *= -1.;
                     dy *= -1.;
                 }
-                run(state, world.board, dx, dy);
+                run(state, &world.board, (dx, dy));
+            }
+        }
+    }
+
+    fn position(&self, state: &Self::State) -> Option<(f32, f32)> {
+        Some((state.position.x, state.position.y))
+    }
+
+    fn on_interact(
+        &self,
+        id: usize,
+        state: &mut Self::State,
+        _other: &Self,
+        other_id: usize,
+        other_state: &mut Self::State,
+        world: &Self::World,
+    ) {
+        // In capture the flag, the player in the territory of the other team is caught
+        if let (Some(team), Some(other_team)) = (state.team, other_state.team) {
+            if team != other_team && state.tag == Tag::None && other_state.tag == Tag::None {
+                if world.board.territory(team).contains(other_state.position) {
+                    other_state.tag = Tag::Jailed { rescued: false };
+                } else {
+                    state.tag = Tag::Jailed { rescued: false };
+                }
+                return;
+            }
+        }
+
+        // Nobody is tagged, while the seekers are counting
+        if world.counting() {
+            return;
+        }
+
+        // "It" tags the first agent in reach, which has not most recently tagged this "It" and is
+        // not safe in a base, and frozen agents are rescued by any other agent, who is not tagged.
+        // Jailed agents are only rescued by their team mates.
+        let team_mates = state.team.is_some() && state.team == other_state.team;
+        match (&mut state.tag, &mut other_state.tag) {
+            (Tag::It(next @ None), Tag::None)
+                if !world.is_recent(id, other_id)
+                    && !world.is_safe(other_id, other_state.position) =>
+            {
+                *next = Some(other_id);
+            }
+            (Tag::None, Tag::It(next @ None))
+                if !world.is_recent(other_id, id) && !world.is_safe(id, state.position) =>
+            {
+                *next = Some(id);
+            }
+            (Tag::None, Tag::Frozen { rescued }) | (Tag::Frozen { rescued }, Tag::None) => {
+                *rescued = true;
+            }
+            (Tag::None, Tag::Jailed { rescued }) | (Tag::Jailed { rescued }, Tag::None)
+                if team_mates =>
+            {
+                *rescued = true;
             }
+            _ => {}
         }
     }
 }
@@ -2,11 +2,12 @@ use std::{
     fs::{File, OpenOptions},
     io::{self, BufReader, BufWriter},
     ops::Range,
+    path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::Board;
+use crate::{agent::Team, map, Board};
 
 /// Configuration for player properties and behaviors
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -28,27 +29,116 @@ impl Default for AgentConfig {
     }
 }
 
+/// The rules of the game.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// A tagged player becomes "It".
+    #[default]
+    Classic,
+    /// A tagged player is frozen in place, until another player touches them.
+    Freeze,
+    /// A tagged player becomes another "It", while the tagging player stays "It".
+    Infection,
+    /// Two teams try to steal the flag of the other team and carry it into their own territory.
+    CaptureTheFlag,
+    /// The "It"s count, while the other players hide, and search them afterwards.
+    HideAndSeek,
+}
+
 /// Configuration for the Tag game
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     pub board: Board,
+    /// A map file, which replaces the board. A relative path starts at the configuration file.
+    #[serde(default)]
+    pub map: Option<PathBuf>,
     pub num_players: usize,
+    /// The rules of the game.
+    #[serde(default)]
+    pub mode: Mode,
+    /// The number of players, which are "It" at the same time.
+    #[serde(default = "Config::default_its")]
+    pub its: usize,
+    /// If set, the "It"s split up the other players instead of all chasing the nearest one.
+    #[serde(default)]
+    pub cooperative: bool,
+    /// The number of ticks, after which the game ends.
+    #[serde(default)]
+    pub time_limit: Option<u64>,
+    /// The number of tags, after which the game ends.
+    #[serde(default)]
+    pub tag_limit: Option<u32>,
+    /// The number of ticks, the seekers count in hide-and-seek.
+    #[serde(default = "Config::default_counting_ticks")]
+    pub counting_ticks: u64,
+    /// The distance, in which players see each other in hide-and-seek.
+    #[serde(default = "Config::default_view_distance")]
+    pub view_distance: f32,
+    /// The number of ticks, a player is safe in a base.
+    #[serde(default = "Config::default_base_ticks")]
+    pub base_ticks: u64,
     pub step: u32,
     pub agents: AgentConfig,
+    /// The seed of the game. A random seed is picked if not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The number of ticks, which can be rewound.
+    #[serde(default = "Config::default_history")]
+    pub history: usize,
+    /// The number of ticks simulated per second, while the game is running.
+    #[serde(default = "Config::default_ticks_per_second")]
+    pub ticks_per_second: f64,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             board: Board::default(),
+            map: None,
             num_players: 10,
+            mode: Mode::default(),
+            its: Self::default_its(),
+            cooperative: false,
+            time_limit: None,
+            tag_limit: None,
+            counting_ticks: Self::default_counting_ticks(),
+            view_distance: Self::default_view_distance(),
+            base_ticks: Self::default_base_ticks(),
             step: 1,
             agents: AgentConfig::default(),
+            seed: None,
+            history: Self::default_history(),
+            ticks_per_second: Self::default_ticks_per_second(),
         }
     }
 }
 
 impl Config {
+    const fn default_its() -> usize {
+        1
+    }
+
+    const fn default_counting_ticks() -> u64 {
+        30
+    }
+
+    const fn default_view_distance() -> f32 {
+        15.
+    }
+
+    const fn default_base_ticks() -> u64 {
+        20
+    }
+
+    const fn default_history() -> usize {
+        1000
+    }
+
+    const fn default_ticks_per_second() -> f64 {
+        10.
+    }
+
     /// Loads the configuration file or creates it, if it does not exist.
     pub fn load() -> Result<Self, io::Error> {
         let config_file_path = std::env::current_dir()?
@@ -56,12 +146,18 @@ impl Config {
             .join("tag")
             .join("config.json");
         if let Ok(file) = File::open(&config_file_path) {
-            Ok(serde_json::from_reader(BufReader::new(file))?)
+            let mut config: Self = serde_json::from_reader(BufReader::new(file))?;
+            if let Some(directory) = config_file_path.parent() {
+                config.load_map(directory)?;
+            }
+            config.validate()?;
+            Ok(config)
         } else {
             let writer = BufWriter::new(
                 OpenOptions::new()
                     .create(true)
                     .write(true)
+                    .truncate(true)
                     .open(config_file_path)?,
             );
             let config = Self::default();
@@ -69,4 +165,74 @@ impl Config {
             Ok(config)
         }
     }
+
+    /// Replaces the board by the map file, if one is set. A relative path is resolved in
+    /// `directory`.
+    pub fn load_map(&mut self, directory: &Path) -> Result<(), io::Error> {
+        if let Some(map) = &self.map {
+            self.board = map::load(directory.join(map)).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("failed to load the map {}: {}", map.display(), error),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Checks, that the game can be played with this configuration.
+    pub fn validate(&self) -> Result<(), io::Error> {
+        if self.its == 0 || self.its >= self.num_players {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the number of \"It\"s must be between 1 and {}",
+                    self.num_players.saturating_sub(1)
+                ),
+            ));
+        }
+        let teams = if self.mode == Mode::CaptureTheFlag {
+            vec![Some(Team::Red), Some(Team::Blue)]
+        } else {
+            vec![None]
+        };
+        if !teams
+            .into_iter()
+            .all(|team| self.board.has_free_field(team))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the players need a field without an obstacle to start on",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Mode};
+    use crate::map;
+
+    #[test]
+    fn test_validate_blocked_board() {
+        let config = Config {
+            board: map::parse("####\n####").unwrap(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+
+        // In capture the flag, both teams need a free field in their territory
+        let config = Config {
+            board: map::parse("..##\n..##").unwrap(),
+            mode: Mode::CaptureTheFlag,
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+        let config = Config {
+            board: map::parse("..#.\n..##").unwrap(),
+            ..config
+        };
+        assert!(config.validate().is_ok());
+    }
 }
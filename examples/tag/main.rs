@@ -2,114 +2,215 @@
 
 mod agent;
 mod config;
+mod map;
 mod output;
+mod replay;
+mod result;
 mod world;
 
 use std::{
-    io::{stdin, stdout, Write},
-    time::Instant,
+    env,
+    fs::File,
+    io::{self, stdout, BufWriter, Write},
+    thread,
+    time::{Duration, Instant},
 };
 
-use agent::{Position, Properties};
-use rand::Rng;
-use termion::{event::Key, input::TermRead};
+use rand::{thread_rng, Rng};
+use termion::{async_stdin, event::Key, input::TermRead};
 
-use tag_game::Simulation;
+use tag_game::SimulationRunner;
 
 use crate::{
-    agent::{AgentState, Tag, TagAgent},
-    config::Config,
-    output::Output,
-    world::{Board, TagWorld},
+    config::{Config, Mode},
+    output::{Leaderboard, Output},
+    replay::{Recorder, Replay},
+    result::MatchResult,
+    world::{create_simulation, Board},
 };
 
-fn main() -> Result<(), std::io::Error> {
-    let config = Config::load()?;
+fn main() -> Result<(), io::Error> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|index| args.get(index + 1))
+    };
 
-    // Initialize random generator
-    let mut rng = rand::thread_rng();
+    // Watch a recorded game
+    if let Some(path) = option("--replay") {
+        return Replay::open(path)?.play();
+    }
 
-    // Initialize world
-    let world = TagWorld {
-        board: config.board,
-        current_it: rng.gen_range(0..config.num_players),
-        recent_it: None,
+    let config = Config::load()?;
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    let simulation = create_simulation(&config, seed);
+
+    // Optionally record the game
+    let mut recorder = match option("--record") {
+        Some(path) => {
+            let mut recorder = Recorder::create(path, &config, seed, simulation.agents())?;
+            recorder.record(simulation.tick(), simulation.agents())?;
+            Some(recorder)
+        }
+        None => None,
     };
 
-    // create the simulation with the created world
-    let mut simulation = Simulation::new(world);
-
-    // create the agents
-    // the world already has the information, which agent is "It" at startup
-    // The agent will update the state as soon as the simulation begins
-    for _ in 0..config.num_players {
-        simulation.add_agent(
-            TagAgent,
-            AgentState {
-                tag: Tag::None,
-                position: Position {
-                    x: rng.gen_range(0. ..config.board.width as f32),
-                    y: rng.gen_range(0. ..config.board.height as f32),
-                },
-                properties: Properties {
-                    untagged_deciding: rng.gen_range(config.agents.untagged_deciding.clone()),
-                    tagged_deciding: rng.gen_range(config.agents.tagged_deciding.clone()),
-                    untagged_speed_multiplied: rng
-                        .gen_range(config.agents.untagged_speed_multiplied.clone()),
-                    tagged_speed_multiplied: rng
-                        .gen_range(config.agents.tagged_speed_multiplied.clone()),
-                },
-            },
-        );
-    }
+    // Run the simulation in the background, so slow drawing does not block it
+    let runner = SimulationRunner::spawn(simulation);
+    let snapshots = runner.subscribe();
+    let mut ticks_per_second = config.ticks_per_second;
+    let mut running = false;
+    let mut show_view = false;
+    runner.set_ticks_per_second(Some(ticks_per_second));
+    runner.step(1);
 
     // create the viewer to spectate the game
-    let mut viewer = Output::new(config.board)?;
-    simulation.update();
-    viewer.draw_players(simulation.agents());
-    stdout().flush()?;
-
-    for c in stdin().keys() {
-        match c? {
-            Key::Char('q') | Key::Esc | Key::Ctrl('c' | 'd') => break,
-            Key::Char('t') => {
-                let start = Instant::now();
-
-                // We may skip some frames being shown
-                // as terminals tend to be slow
-                for _ in 0..config.step {
-                    // Advance simulation by one tick
-                    simulation.update();
+    let mut viewer = Output::new(config.board.clone())?;
+    let mut leaderboard = Leaderboard::default();
+    viewer.show_leaderboard(leaderboard);
+    let mut keys = async_stdin().keys();
+    let mut drawn = (0, Instant::now());
+
+    'game: loop {
+        let snapshot = runner.snapshot();
+        for key in &mut keys {
+            match key? {
+                Key::Char('q') | Key::Esc | Key::Ctrl('c' | 'd') => break 'game,
+                // We may skip some frames being shown as terminals tend to be slow
+                Key::Char('t') => {
+                    running = false;
+                    runner.step(config.step as u64);
+                }
+                Key::Char(' ') => {
+                    running = !running;
+                    if running {
+                        runner.resume();
+                    } else {
+                        runner.pause();
+                    }
+                }
+                Key::Char('+') => {
+                    ticks_per_second *= 2.;
+                    runner.set_ticks_per_second(Some(ticks_per_second));
+                }
+                Key::Char('-') => {
+                    ticks_per_second = (ticks_per_second / 2.).max(1.);
+                    runner.set_ticks_per_second(Some(ticks_per_second));
+                }
+                Key::Char('b') => {
+                    // Rewind the simulation. Pressing 't' afterwards simulates the same ticks again
+                    running = false;
+                    runner.pause();
+                    let step = config.step as u64;
+                    runner.apply(move |simulation| {
+                        simulation.rewind(step);
+                    });
+                }
+                Key::Char('v') => {
+                    // Redraw right away, so the field of view also toggles while paused
+                    show_view = !show_view;
+                    viewer.draw_players(snapshot.agents());
+                    viewer.draw_flags(snapshot.world(), snapshot.agents());
+                    if show_view {
+                        viewer.draw_field_of_view(snapshot.world(), snapshot.agents());
+                    }
                 }
+                Key::Char('m') => {
+                    // Sort the leaderboard by the next statistic
+                    leaderboard = leaderboard.next();
+                    viewer.show_leaderboard(leaderboard);
+                    viewer.draw_leaderboard(snapshot.world(), snapshot.agents());
+                }
+                Key::Left | Key::Char('h') => viewer.scroll_left(snapshot.agents()),
+                Key::Down | Key::Char('j') => viewer.scroll_down(snapshot.agents()),
+                Key::Up | Key::Char('k') => viewer.scroll_up(snapshot.agents()),
+                Key::Right | Key::Char('l') => viewer.scroll_right(snapshot.agents()),
+                _ => {}
+            }
+        }
 
-                let calc_time = start.elapsed();
-                let start = Instant::now();
+        // Record every tick, even if it's not drawn
+        for snapshot in snapshots.try_iter() {
+            if let Some(recorder) = &mut recorder {
+                recorder.record(snapshot.tick(), snapshot.agents())?;
+            }
+        }
 
-                // Draw players on board
-                viewer.draw_players(simulation.agents());
+        if snapshot.tick() != drawn.0 {
+            let start = Instant::now();
 
-                let draw_time = start.elapsed();
-                viewer.draw_time(calc_time, draw_time, config.step)?;
-                stdout().flush()?;
+            // Draw players on board
+            viewer.draw_players(snapshot.agents());
+            viewer.draw_flags(snapshot.world(), snapshot.agents());
+            if show_view {
+                viewer.draw_field_of_view(snapshot.world(), snapshot.agents());
             }
-            Key::Left | Key::Char('h') => viewer.scroll_left(simulation.agents()),
-            Key::Down | Key::Char('j') => viewer.scroll_down(simulation.agents()),
-            Key::Up | Key::Char('k') => viewer.scroll_up(simulation.agents()),
-            Key::Right | Key::Char('l') => viewer.scroll_right(simulation.agents()),
-            _ => {}
+            viewer.draw_leaderboard(snapshot.world(), snapshot.agents());
+
+            let draw_time = start.elapsed();
+            let ticks = snapshot.tick().saturating_sub(drawn.0);
+            #[allow(clippy::cast_possible_truncation)]
+            viewer.draw_time(drawn.1.elapsed(), draw_time, ticks as u32)?;
+            drawn = (snapshot.tick(), Instant::now());
+
+            // Inspect some values
+            let world = snapshot.world();
+            let players = if world.mode == Mode::CaptureTheFlag {
+                format!("score: red {}, blue {}", world.scores[0], world.scores[1])
+            } else {
+                let its = world
+                    .its
+                    .iter()
+                    .map(|it| {
+                        let position = snapshot.agents()[it.id].1.position;
+                        format!("{} at ({},{})", it.id, position.x + 1., position.y + 1.)
+                    })
+                    .collect::<Vec<_>>();
+                format!("current \"It\": {}", its.join(", "))
+            };
+            print!(
+                " - tick {}, {}, {} tps{}    ",
+                snapshot.tick(),
+                players,
+                ticks_per_second,
+                if world.finished {
+                    " (game over)"
+                } else if running {
+                    ""
+                } else {
+                    " (paused)"
+                },
+            );
+
+            stdout().flush()?;
         }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let simulation = runner.stop();
+    if let Some(recorder) = &mut recorder {
+        recorder.flush()?;
+    }
 
-        // Inspect some values
-        let current_it_id = simulation.world().current_it;
-        let (_, current_it) = &simulation.agents()[current_it_id];
-        print!(
-            " - current \"It\": {:?} at position ({},{})    ",
-            current_it_id,
-            current_it.position.x + 1.,
-            current_it.position.y + 1.
-        );
-
-        stdout().flush()?;
+    // Leave the terminal screen and print, who has won
+    drop(viewer);
+    let result = MatchResult::new(simulation.tick(), simulation.world(), simulation.agents());
+    print!("{}", result);
+    if let Some(path) = option("--result") {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &result)?;
+        writer.flush()?;
+    }
+
+    // Optionally export the number of "It"s per tick in infection tag
+    if let Some(path) = option("--infection-curve") {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "tick,its")?;
+        for (tick, its) in simulation.world().infection_curve().into_iter().enumerate() {
+            writeln!(writer, "{},{}", tick, its)?;
+        }
+        writer.flush()?;
     }
     Ok(())
 }
@@ -0,0 +1,385 @@
+use std::{cmp::Reverse, collections::BinaryHeap, convert::TryFrom, fs, io, path::Path};
+
+use crate::{
+    agent::Position,
+    world::{Area, Base, Board, Terrain, TerrainArea},
+};
+
+/// Loads the board from a map file.
+///
+/// A *.json* file contains the board as in the configuration. Any other file is an ASCII map,
+/// in which every character is one field: `#` is a wall, `+` belongs to a base, `~` is mud, `:`
+/// is sand, `=` is a track, and every other character is grass.
+pub fn load(path: impl AsRef<Path>) -> io::Result<Board> {
+    let path = path.as_ref();
+    let map = fs::read_to_string(path)?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        Ok(serde_json::from_str(&map)?)
+    } else {
+        parse(&map)
+    }
+}
+
+/// Parses an ASCII map. Neighboring fields of the same kind in a row are merged into one
+/// rectangle.
+#[allow(clippy::cast_precision_loss)]
+pub fn parse(map: &str) -> io::Result<Board> {
+    let rows = map
+        .lines()
+        .map(|line| line.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let (width, height) = match (u16::try_from(width), u16::try_from(rows.len())) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the map must have between 1 and {} rows and columns",
+                    u16::MAX
+                ),
+            ))
+        }
+    };
+
+    let mut board = Board {
+        width,
+        height,
+        ..Board::default()
+    };
+    for (y, row) in rows.iter().enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let field = row[x];
+            let length = row[x..].iter().take_while(|&&other| other == field).count();
+            let area = Area {
+                x: x as f32,
+                y: y as f32,
+                width: length as f32,
+                height: 1.,
+            };
+            let terrain = |terrain| TerrainArea { area, terrain };
+            match field {
+                '#' => board.obstacles.push(area),
+                '+' => board.bases.push(Base::Rectangle(area)),
+                '~' => board.terrain.push(terrain(Terrain::Mud)),
+                ':' => board.terrain.push(terrain(Terrain::Sand)),
+                '=' => board.terrain.push(terrain(Terrain::Track)),
+                _ => {}
+            }
+            x += length;
+        }
+    }
+    Ok(board)
+}
+
+/// The fields of the board, which are used to find the fastest paths around the obstacles and
+/// through the terrain.
+#[derive(Clone, Debug)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    /// If a field is blocked, row by row
+    blocked: Vec<bool>,
+    /// The speed of the terrain of every field, row by row
+    speeds: Vec<f32>,
+    /// The highest speed on the board
+    fastest: f32,
+}
+
+impl Grid {
+    /// The cost of a step to a horizontal or vertical neighbor on grass.
+    const STRAIGHT: u32 = 100;
+    /// The cost of a diagonal step on grass, roughly `STRAIGHT` times √2.
+    const DIAGONAL: u32 = 141;
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(board: &Board) -> Self {
+        let (width, height) = (board.width as usize, board.height as usize);
+        let centers = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| Position::new(x as f32 + 0.5, y as f32 + 0.5))
+            .collect::<Vec<_>>();
+        let blocked = centers
+            .iter()
+            .map(|&center| board.is_blocked(center))
+            .collect();
+        let speeds = centers
+            .iter()
+            .map(|&center| board.terrain_at(center).speed())
+            .collect::<Vec<_>>();
+        let fastest = speeds
+            .iter()
+            .copied()
+            .fold(Terrain::Grass.speed(), f32::max);
+        Self {
+            width,
+            height,
+            blocked,
+            speeds,
+            fastest,
+        }
+    }
+
+    /// Returns the index of the field at the position.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn field(&self, position: Position) -> usize {
+        let x = (position.x.max(0.) as usize).min(self.width - 1);
+        let y = (position.y.max(0.) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+
+    /// Returns the center of the field.
+    #[allow(clippy::cast_precision_loss)]
+    fn center(&self, field: usize) -> Position {
+        Position::new(
+            (field % self.width) as f32 + 0.5,
+            (field / self.width) as f32 + 0.5,
+        )
+    }
+
+    /// Returns the neighbors of the field, which are not blocked, with the cost to step there,
+    /// which is higher on slower terrain. Diagonal steps may not cut the corner of a blocked
+    /// field.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn neighbors(&self, field: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+        let (x, y) = ((field % self.width) as isize, (field / self.width) as isize);
+        let free = move |x: isize, y: isize| {
+            (0..self.width as isize).contains(&x)
+                && (0..self.height as isize).contains(&y)
+                && !self.blocked[y as usize * self.width + x as usize]
+        };
+        [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ]
+        .iter()
+        .copied()
+        .filter(move |&(dx, dy)| free(x + dx, y + dy) && free(x + dx, y) && free(x, y + dy))
+        .map(move |(dx, dy)| {
+            let cost = if dx != 0 && dy != 0 {
+                Self::DIAGONAL
+            } else {
+                Self::STRAIGHT
+            };
+            let neighbor = (y + dy) as usize * self.width + (x + dx) as usize;
+            (
+                neighbor,
+                (cost as f32 / self.speeds[neighbor]).round() as u32,
+            )
+        })
+    }
+
+    /// Returns if a horizontal or vertical neighbor of the field is blocked, so the field is
+    /// behind an obstacle from some directions.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn is_cover(&self, field: usize) -> bool {
+        let (x, y) = ((field % self.width) as isize, (field / self.width) as isize);
+        [(0, -1), (-1, 0), (1, 0), (0, 1)]
+            .iter()
+            .map(|&(dx, dy)| (x + dx, y + dy))
+            .any(|(x, y)| {
+                (0..self.width as isize).contains(&x)
+                    && (0..self.height as isize).contains(&y)
+                    && self.blocked[y as usize * self.width + x as usize]
+            })
+    }
+
+    /// Returns the estimated cost from one field to another, which is never too high, as the
+    /// whole way is assumed to be on the fastest terrain.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn estimate(&self, from: usize, to: usize) -> u32 {
+        let dx = (from % self.width).abs_diff(to % self.width);
+        let dy = (from / self.width).abs_diff(to / self.width);
+        let (short, long) = (dx.min(dy) as u32, dx.max(dy) as u32);
+        let cost = Self::DIAGONAL * short + Self::STRAIGHT * (long - short);
+        (cost as f32 / self.fastest).floor() as u32
+    }
+
+    /// Returns the centers of the fields on the fastest path from `from` to `to` using A*,
+    /// without the field of `from`. Returns `None`, if `to` can't be reached.
+    pub fn path(&self, from: Position, to: Position) -> Option<Vec<Position>> {
+        let (start, goal) = (self.field(from), self.field(to));
+        let mut costs = vec![u32::MAX; self.blocked.len()];
+        let mut previous = vec![None; self.blocked.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0;
+        open.push(Reverse((self.estimate(start, goal), start)));
+
+        while let Some(Reverse((_, field))) = open.pop() {
+            if field == goal {
+                let mut path = vec![self.center(goal)];
+                let mut field = goal;
+                while let Some(before) = previous[field].filter(|&before| before != start) {
+                    path.push(self.center(before));
+                    field = before;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (neighbor, cost) in self.neighbors(field) {
+                let cost = costs[field] + cost;
+                if cost < costs[neighbor] {
+                    costs[neighbor] = cost;
+                    previous[neighbor] = Some(field);
+                    open.push(Reverse((cost + self.estimate(neighbor, goal), neighbor)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the center of the field next to an obstacle, which can be reached fastest from
+    /// `from` and for which `hidden` holds, using Dijkstra's algorithm. Returns `None`, if there
+    /// is no such field.
+    pub fn hiding_place(
+        &self,
+        from: Position,
+        hidden: impl Fn(Position) -> bool,
+    ) -> Option<Position> {
+        let start = self.field(from);
+        let mut costs = vec![u32::MAX; self.blocked.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0;
+        open.push(Reverse((0, start)));
+
+        while let Some(Reverse((cost, field))) = open.pop() {
+            if cost > costs[field] {
+                continue;
+            }
+            if self.is_cover(field) && hidden(self.center(field)) {
+                return Some(self.center(field));
+            }
+            for (neighbor, step) in self.neighbors(field) {
+                let cost = cost + step;
+                if cost < costs[neighbor] {
+                    costs[neighbor] = cost;
+                    open.push(Reverse((cost, neighbor)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the position, to which an agent at `from` runs on the way to `to`. This is `to`
+    /// itself, if the agent can run there straight, or else the farthest field on the fastest
+    /// path, which can be reached straight from `from`.
+    pub fn waypoint(&self, board: &Board, from: Position, to: Position) -> Position {
+        if board.straight_way(from, to) {
+            return to;
+        }
+        self.path(from, to)
+            .and_then(|path| {
+                let visible = path
+                    .iter()
+                    .take_while(|&&field| board.straight_way(from, field))
+                    .count();
+                path.get(visible.saturating_sub(1)).copied()
+            })
+            .unwrap_or(to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{parse, Grid};
+    use crate::{
+        agent::Position,
+        world::{Area, Base, Terrain, TerrainArea},
+    };
+
+    fn area(x: f32, y: f32, width: f32) -> Area {
+        Area {
+            x,
+            y,
+            width,
+            height: 1.,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let board = parse("##..+\n.~~:=\n.").unwrap();
+        assert_eq!((board.width, board.height), (5, 3));
+        assert_eq!(board.obstacles, vec![area(0., 0., 2.)]);
+        assert_eq!(board.bases, vec![Base::Rectangle(area(4., 0., 1.))]);
+        let terrain = |area, terrain| TerrainArea { area, terrain };
+        assert_eq!(
+            board.terrain,
+            vec![
+                terrain(area(1., 1., 2.), Terrain::Mud),
+                terrain(area(3., 1., 1.), Terrain::Sand),
+                terrain(area(4., 1., 1.), Terrain::Track),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let error = parse("").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = parse(&".".repeat(usize::from(u16::MAX) + 1)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_path() {
+        let board = parse(".....\n.###.\n.....").unwrap();
+        let grid = Grid::new(&board);
+        let (from, to) = (Position::new(2.5, 0.5), Position::new(2.5, 2.5));
+        let path = grid.path(from, to).unwrap();
+        // Around the end of the wall without cutting its corners
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.last(), Some(&to));
+        assert!(path.iter().all(|&field| !board.is_blocked(field)));
+        assert!(path
+            .windows(2)
+            .all(|pair| (pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs() == 1.));
+        assert_ne!(grid.waypoint(&board, from, to), to);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let board = parse("..#..\n..#..").unwrap();
+        let grid = Grid::new(&board);
+        let (from, to) = (Position::new(0.5, 0.5), Position::new(4.5, 1.5));
+        assert_eq!(grid.path(from, to), None);
+        assert_eq!(grid.waypoint(&board, from, to), to);
+    }
+
+    #[test]
+    fn test_hiding_place() {
+        let board = parse(".....\n..#..\n.....").unwrap();
+        let grid = Grid::new(&board);
+        let seeker = Position::new(0.5, 1.5);
+        let hidden = |place| !board.line_of_sight(seeker, place);
+        assert_eq!(
+            grid.hiding_place(Position::new(0.5, 0.5), hidden),
+            Some(Position::new(3.5, 1.5))
+        );
+
+        let board = parse(".....\n.....").unwrap();
+        let grid = Grid::new(&board);
+        assert_eq!(grid.hiding_place(Position::new(0.5, 0.5), |_| true), None);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    cmp::Ordering,
     io::{stdout, Error, Stdout, Write},
-    iter::repeat,
+    iter::repeat_n,
     time::Duration,
 };
 
@@ -13,10 +14,98 @@ use termion::{
 };
 
 use crate::{
-    agent::{AgentState, Position, Tag, TagAgent},
-    world::Board,
+    agent::{AgentState, Position, Tag, TagAgent, Team},
+    config::Mode,
+    world::{Board, PlayerStats, TagWorld, Terrain},
 };
 
+/// Returns the color of the players of `team`.
+fn team_color(team: Team) -> &'static str {
+    match team {
+        Team::Red => color::LightRed.fg_str(),
+        Team::Blue => color::LightBlue.fg_str(),
+    }
+}
+
+/// Returns the character and the color, which represent the player.
+fn glyph(state: &AgentState) -> (char, &'static str) {
+    match (state.tag, state.team) {
+        (Tag::None, Some(team)) => ('#', team_color(team)),
+        (Tag::Jailed { .. }, Some(team)) => ('x', team_color(team)),
+        (Tag::It(_), _) => ('@', color::Red.fg_str()),
+        (Tag::None | Tag::Jailed { .. }, _) => ('#', color::Green.fg_str()),
+        (Tag::Frozen { .. }, _) => ('*', color::Cyan.fg_str()),
+        (Tag::Found, _) => ('o', color::LightBlack.fg_str()),
+    }
+}
+
+/// Returns the background color of the terrain. Grass keeps the background of the terminal.
+fn terrain_color(terrain: Terrain) -> String {
+    match terrain {
+        Terrain::Grass => color::Reset.bg_str().to_string(),
+        Terrain::Mud => color::Bg(color::Rgb(90, 60, 30)).to_string(),
+        Terrain::Sand => color::Bg(color::Rgb(130, 115, 70)).to_string(),
+        Terrain::Track => color::Bg(color::Rgb(80, 80, 80)).to_string(),
+    }
+}
+
+/// The width of the leaderboard next to the board in columns.
+const PANEL_WIDTH: u16 = 28;
+
+/// The statistic, by which the players on the leaderboard are sorted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Leaderboard {
+    /// The shortest time as "It" first
+    #[default]
+    TicksAsIt,
+    /// The most tags first
+    Tags,
+    /// The longest evasion streak first
+    LongestEvasion,
+    /// The longest distance run first
+    Distance,
+}
+
+impl Leaderboard {
+    /// Returns the statistic shown after this one.
+    pub fn next(self) -> Self {
+        match self {
+            Self::TicksAsIt => Self::Tags,
+            Self::Tags => Self::LongestEvasion,
+            Self::LongestEvasion => Self::Distance,
+            Self::Distance => Self::TicksAsIt,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::TicksAsIt => "ticks as It",
+            Self::Tags => "tags",
+            Self::LongestEvasion => "longest evasion",
+            Self::Distance => "distance",
+        }
+    }
+
+    /// Orders two players, the better one first.
+    fn compare(self, a: &PlayerStats, b: &PlayerStats) -> Ordering {
+        match self {
+            Self::TicksAsIt => a.ticks_as_it.cmp(&b.ticks_as_it),
+            Self::Tags => b.tags.cmp(&a.tags),
+            Self::LongestEvasion => b.longest_evasion.cmp(&a.longest_evasion),
+            Self::Distance => b.distance.total_cmp(&a.distance),
+        }
+    }
+
+    fn value(self, stats: &PlayerStats) -> String {
+        match self {
+            Self::TicksAsIt => stats.ticks_as_it.to_string(),
+            Self::Tags => stats.tags.to_string(),
+            Self::LongestEvasion => stats.longest_evasion.to_string(),
+            Self::Distance => format!("{:.1}", stats.distance),
+        }
+    }
+}
+
 /// Simple abstraction over a pixel.
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Pixel {
@@ -43,6 +132,11 @@ pub struct Output {
     last_ups: Vec<u32>,
     last_draw_times: Vec<Duration>,
     tick: u8,
+    help: &'static str,
+    /// The statistic of the leaderboard, if it's shown
+    leaderboard: Option<Leaderboard>,
+    /// The lines of the leaderboard, which were drawn last
+    panel: Vec<String>,
 }
 
 impl Output {
@@ -54,21 +148,55 @@ impl Output {
             terminal_size: terminal_size()?,
             drawn_positions: Vec::new(),
             scroll: (1, 1),
-            last_ups: repeat(0).take(10).collect(),
-            last_draw_times: repeat(Duration::default()).take(10).collect(),
+            last_ups: repeat_n(0, 10).collect(),
+            last_draw_times: repeat_n(Duration::default(), 10).collect(),
             tick: 0,
+            help: "q: Quit, t: Step, space: Run, +/-: Speed, b: Back, h/j/k/l: Scroll, v: View, m: Leaderboard",
+            leaderboard: None,
+            panel: Vec::new(),
         };
 
         output.draw_borders();
         Ok(output)
     }
 
+    /// Sets the key bindings shown above the board.
+    pub fn set_help(&mut self, help: &'static str) {
+        self.help = help;
+        Self::clear();
+        self.draw_borders();
+    }
+
+    /// Shows the leaderboard next to the board sorted by the statistic.
+    pub fn show_leaderboard(&mut self, leaderboard: Leaderboard) {
+        let shown = self.leaderboard.replace(leaderboard).is_some();
+        if !shown {
+            Self::clear();
+            self.draw_borders();
+        }
+    }
+
+    /// Returns the number of columns, in which the board is drawn.
+    fn board_columns(&self) -> u16 {
+        if self.leaderboard.is_some() {
+            self.terminal_size.0.saturating_sub(PANEL_WIDTH)
+        } else {
+            self.terminal_size.0
+        }
+    }
+
+    /// Returns the cursor position right after the key bindings.
+    #[allow(clippy::cast_possible_truncation)]
+    fn info_cursor(&self) -> cursor::Goto {
+        cursor::Goto(self.help.chars().count() as u16 + 5, 1)
+    }
+
     fn after_scrolling(&mut self, states: &[(TagAgent, AgentState)]) {
         self.drawn_positions.clear();
         Self::clear();
         self.draw_borders();
         self.draw_players(states);
-        print!("{}{}", color::Reset.fg_str(), cursor::Goto(39, 1));
+        print!("{}{}", color::Reset.fg_str(), self.info_cursor());
     }
 
     /// Scroll the board up
@@ -99,7 +227,7 @@ impl Output {
         let x = p.x + self.scroll.0 as f32 + 1.;
         let y = p.y + self.scroll.1 as f32 + 1.;
         if x > 0.
-            && x < self.terminal_size.0 as f32
+            && x < self.board_columns() as f32
             && y > 0.
             && y + 1. < self.terminal_size.1 as f32
         {
@@ -111,6 +239,36 @@ impl Output {
         }
     }
 
+    /// Returns the center of the field drawn at the pixel.
+    #[allow(clippy::cast_precision_loss)]
+    fn pixel_to_position(&self, px: Pixel) -> Position {
+        Position::new(
+            px.x as f32 - self.scroll.0 as f32 - 0.5,
+            px.y as f32 - self.scroll.1 as f32 - 0.5,
+        )
+    }
+
+    /// Draws the character in the color at the pixel on top of the terrain of the field.
+    fn put(&self, px: Pixel, ch: char, color: &str) {
+        print!(
+            "{}{}{}{}{}",
+            cursor::Goto(px.x, px.y),
+            terrain_color(self.board.terrain_at(self.pixel_to_position(px))),
+            color,
+            ch,
+            color::Reset.bg_str()
+        );
+    }
+
+    /// Draws the empty board at the pixel, which is blank or a base, on top of the terrain.
+    fn draw_background(&self, px: Pixel) {
+        if self.board.in_base(self.pixel_to_position(px)) {
+            self.put(px, '░', color::Blue.fg_str());
+        } else {
+            self.put(px, ' ', color::Reset.fg_str());
+        }
+    }
+
     fn draw(&mut self, position: Position, ch: char, color: Option<&'static str>) {
         if let Some(Pixel { x, y }) = self.position_to_pixel(position) {
             if let Some(color) = color {
@@ -154,28 +312,130 @@ impl Output {
             color::Reset.fg_str(),
             cursor::Goto(1, self.terminal_size.1),
             ups as u32,
-            avg_ups as u32,
+            avg_ups,
             draw_time,
             avg_draw_times,
-            cursor::Goto(39, 1),
+            self.info_cursor(),
+        )
+    }
+
+    /// Draws a status line below the board
+    pub fn draw_status(&mut self, status: &str) -> Result<(), Error> {
+        write!(
+            self.screen,
+            "{}{}{}{}",
+            color::Reset.fg_str(),
+            cursor::Goto(1, self.terminal_size.1),
+            clear::CurrentLine,
+            status,
         )
     }
 
     /// Draws the player onto the board
     pub fn draw_players(&mut self, states: &[(TagAgent, AgentState)]) {
-        for Pixel { x, y } in &self.drawn_positions {
-            print!("{} ", cursor::Goto(*x, *y));
+        for px in &self.drawn_positions {
+            self.draw_background(*px);
         }
         self.drawn_positions.clear();
         for (_id, state) in states {
             if let Some(px) = self.position_to_pixel(state.position) {
                 self.drawn_positions.push(px);
-                match state.tag {
-                    Tag::It(_) => print!("{}{}@", cursor::Goto(px.x, px.y), color::Red.fg_str()),
-                    Tag::Recent => {
-                        print!("{}{}%", cursor::Goto(px.x, px.y), color::Yellow.fg_str());
+                let (glyph, color) = glyph(state);
+                self.put(px, glyph, color);
+            }
+        }
+    }
+
+    /// Draws the leaderboard next to the board, which lists the players sorted by the selected
+    /// statistic.
+    pub fn draw_leaderboard(&mut self, world: &TagWorld, states: &[(TagAgent, AgentState)]) {
+        if let Some(leaderboard) = self.leaderboard {
+            let mut players = (0..states.len()).collect::<Vec<_>>();
+            players.sort_by(|&a, &b| {
+                leaderboard
+                    .compare(&world.stats[a], &world.stats[b])
+                    .then(a.cmp(&b))
+            });
+            self.panel = vec![
+                "Leaderboard (m: next)".to_string(),
+                format!("by {}", leaderboard.name()),
+                String::new(),
+            ];
+            for (place, id) in players.into_iter().enumerate() {
+                let (glyph, color) = glyph(&states[id].1);
+                self.panel.push(format!(
+                    "{:>2}. {}{}{} {:>3} {:>13}",
+                    place + 1,
+                    color,
+                    glyph,
+                    color::Reset.fg_str(),
+                    id,
+                    leaderboard.value(&world.stats[id]),
+                ));
+            }
+            self.draw_panel();
+        }
+    }
+
+    /// Draws the lines of the leaderboard, which fit into the terminal.
+    #[allow(clippy::cast_possible_truncation)]
+    fn draw_panel(&self) {
+        if self.leaderboard.is_none() {
+            return;
+        }
+        let x = self.board_columns() + 2;
+        let rows = self.terminal_size.1.saturating_sub(3) as usize;
+        for (row, line) in self.panel.iter().take(rows).enumerate() {
+            let y = row as u16 + 2;
+            print!(
+                "{}{}{}{}{}",
+                color::Reset.fg_str(),
+                cursor::Goto(x, y),
+                " ".repeat(PANEL_WIDTH as usize - 2),
+                cursor::Goto(x, y),
+                line
+            );
+        }
+    }
+
+    /// Draws the flags in capture the flag. A carried flag is drawn on top of the carrier.
+    pub fn draw_flags(&mut self, world: &TagWorld, states: &[(TagAgent, AgentState)]) {
+        if world.mode != Mode::CaptureTheFlag {
+            return;
+        }
+        for team in [Team::Red, Team::Blue] {
+            let position = match world.flags[team.index()].carrier {
+                Some(carrier) => states[carrier].1.position,
+                None => self.board.flag(team),
+            };
+            if let Some(px) = self.position_to_pixel(position) {
+                self.drawn_positions.push(px);
+                self.put(px, 'F', team_color(team));
+            }
+        }
+    }
+
+    /// Draws the field of view of the seekers in hide-and-seek around the players.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw_field_of_view(&mut self, world: &TagWorld, states: &[(TagAgent, AgentState)]) {
+        if world.mode != Mode::HideAndSeek {
+            return;
+        }
+        let players = self.drawn_positions.len();
+        for it in &world.its {
+            let seeker = states[it.id].1.position;
+            for y in 0..self.board.height {
+                for x in 0..self.board.width {
+                    let position = Position::new(x as f32 + 0.5, y as f32 + 0.5);
+                    if !world.can_see(seeker, position) {
+                        continue;
+                    }
+                    if let Some(px) = self.position_to_pixel(position) {
+                        if !self.drawn_positions[..players].contains(&px) {
+                            self.drawn_positions.push(px);
+                            self.put(px, '·', color::LightBlack.fg_str());
+                        }
                     }
-                    Tag::None => print!("{}{}#", cursor::Goto(px.x, px.y), color::Green.fg_str()),
                 }
             }
         }
@@ -202,9 +462,34 @@ impl Output {
             None,
         );
 
+        for y in 0..self.board.height {
+            for x in 0..self.board.width {
+                let position = Position::new(x as f32 + 0.5, y as f32 + 0.5);
+                if let Some(px) = self.position_to_pixel(position) {
+                    if self.board.is_blocked(position) {
+                        self.put(px, '█', color::Reset.fg_str());
+                    } else if self.board.in_base(position)
+                        || self.board.terrain_at(position) != Terrain::Grass
+                    {
+                        self.draw_background(px);
+                    }
+                }
+            }
+        }
+
+        if self.leaderboard.is_some() {
+            print!("{}", color::Reset.fg_str());
+            for y in 2..self.terminal_size.1 {
+                print!("{}│", cursor::Goto(self.board_columns(), y));
+            }
+            self.draw_panel();
+        }
+
         print!(
-            "{} q: Quit, t: Update, h/j/k/l: Scroll ",
-            cursor::Goto(3, 1)
+            "{}{} {} ",
+            color::Reset.fg_str(),
+            cursor::Goto(3, 1),
+            self.help
         );
     }
 }
@@ -0,0 +1,373 @@
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, stdout, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use termion::{async_stdin, event::Key, input::TermRead};
+
+use crate::{
+    agent::{AgentState, Position, Properties, Tag, TagAgent, Team},
+    config::Config,
+    output::Output,
+};
+
+/// The version of the replay format written by [`Recorder`].
+const VERSION: u32 = 1;
+
+/// Positions are stored in fixed point with this many steps per field.
+const PRECISION: f32 = 100.;
+
+/// The first line of a replay file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    seed: u64,
+    config: Config,
+    properties: Vec<Properties>,
+    teams: Vec<Option<Team>>,
+}
+
+/// A line in the replay file for every recorded tick.
+///
+/// Only agents, which have moved or changed their tag since the previously written frame, are
+/// stored. Positions are stored as difference to the previous position.
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    tick: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    moved: Vec<(usize, i32, i32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<(usize, u8)>,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn quantize(position: Position) -> (i32, i32) {
+    (
+        (position.x * PRECISION).round() as i32,
+        (position.y * PRECISION).round() as i32,
+    )
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn dequantize((x, y): (i32, i32)) -> Position {
+    Position::new(x as f32 / PRECISION, y as f32 / PRECISION)
+}
+
+fn encode_tag(tag: Tag) -> u8 {
+    match tag {
+        Tag::None => 0,
+        Tag::It(_) => 1,
+        Tag::Frozen { .. } => 2,
+        Tag::Jailed { .. } => 3,
+        Tag::Found => 4,
+    }
+}
+
+fn decode_tag(tag: u8) -> io::Result<Tag> {
+    match tag {
+        0 => Ok(Tag::None),
+        1 => Ok(Tag::It(None)),
+        2 => Ok(Tag::Frozen { rescued: false }),
+        3 => Ok(Tag::Jailed { rescued: false }),
+        4 => Ok(Tag::Found),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown tag {}", tag),
+        )),
+    }
+}
+
+/// Writes the ticks of a running game to a replay file.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    last: Vec<((i32, i32), u8)>,
+}
+
+impl Recorder {
+    /// Creates the replay file and writes the header.
+    pub fn create(
+        path: impl AsRef<Path>,
+        config: &Config,
+        seed: u64,
+        agents: &[(TagAgent, AgentState)],
+    ) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = Header {
+            version: VERSION,
+            seed,
+            config: config.clone(),
+            properties: agents
+                .iter()
+                .map(|(_, state)| state.properties.clone())
+                .collect(),
+            teams: agents.iter().map(|(_, state)| state.team).collect(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
+        Ok(Self {
+            writer,
+            last: Vec::new(),
+        })
+    }
+
+    /// Writes the state of the agents after `tick` ticks.
+    ///
+    /// Recording a tick, which was already recorded, e.g. after rewinding the simulation,
+    /// replaces the recorded tick and all ticks afterwards.
+    pub fn record(&mut self, tick: u64, agents: &[(TagAgent, AgentState)]) -> io::Result<()> {
+        let mut frame = Frame {
+            tick,
+            moved: Vec::new(),
+            tags: Vec::new(),
+        };
+        self.last
+            .resize(agents.len(), ((0, 0), encode_tag(Tag::None)));
+        for (id, ((_, state), (position, tag))) in agents.iter().zip(&mut self.last).enumerate() {
+            let new_position = quantize(state.position);
+            if new_position != *position {
+                frame
+                    .moved
+                    .push((id, new_position.0 - position.0, new_position.1 - position.1));
+                *position = new_position;
+            }
+            let new_tag = encode_tag(state.tag);
+            if new_tag != *tag {
+                frame.tags.push((id, new_tag));
+                *tag = new_tag;
+            }
+        }
+        serde_json::to_writer(&mut self.writer, &frame)?;
+        writeln!(self.writer)
+    }
+
+    /// Writes all buffered frames to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A recorded game loaded from a replay file.
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    first_tick: u64,
+    frames: Vec<Vec<(TagAgent, AgentState)>>,
+}
+
+impl Replay {
+    /// Loads and decodes a replay file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = serde_json::from_str(
+            &lines
+                .next()
+                .ok_or_else(|| invalid("empty replay file".to_string()))??,
+        )?;
+        if header.version != VERSION {
+            return Err(invalid(format!(
+                "unsupported replay version {}",
+                header.version
+            )));
+        }
+        if header.teams.len() != header.properties.len() {
+            return Err(invalid("number of teams and players differ".to_string()));
+        }
+
+        let mut current = header
+            .properties
+            .into_iter()
+            .zip(header.teams)
+            .map(|(properties, team)| {
+                let state = AgentState {
+                    tag: Tag::None,
+                    team,
+                    position: Position::new(0., 0.),
+                    properties,
+                };
+                (TagAgent, state)
+            })
+            .collect::<Vec<_>>();
+        let mut positions = vec![(0, 0); current.len()];
+        let mut first_tick = None;
+        let mut frames = Vec::new();
+        for line in lines {
+            let frame: Frame = serde_json::from_str(&line?)?;
+            let first_tick = *first_tick.get_or_insert(frame.tick);
+            let index = frame
+                .tick
+                .checked_sub(first_tick)
+                .and_then(|index| usize::try_from(index).ok())
+                .ok_or_else(|| invalid(format!("tick {} before first tick", frame.tick)))?;
+            if index > frames.len() {
+                return Err(invalid(format!("tick {} skips recorded ticks", frame.tick)));
+            }
+            for (id, dx, dy) in frame.moved {
+                let position = positions
+                    .get_mut(id)
+                    .ok_or_else(|| invalid(format!("unknown agent {}", id)))?;
+                *position = (position.0 + dx, position.1 + dy);
+                current[id].1.position = dequantize(*position);
+            }
+            for (id, tag) in frame.tags {
+                current
+                    .get_mut(id)
+                    .ok_or_else(|| invalid(format!("unknown agent {}", id)))?
+                    .1
+                    .tag = decode_tag(tag)?;
+            }
+            // A rewound game overwrites the ticks recorded before
+            frames.truncate(index);
+            frames.push(current.clone());
+        }
+
+        Ok(Self {
+            seed: header.seed,
+            config: header.config,
+            first_tick: first_tick.unwrap_or_default(),
+            frames,
+        })
+    }
+
+    /// Returns the number of recorded ticks.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the tick of the frame at `index`.
+    pub fn tick(&self, index: usize) -> u64 {
+        self.first_tick + index as u64
+    }
+
+    /// Returns the agents in the frame at `index`.
+    pub fn frame(&self, index: usize) -> &[(TagAgent, AgentState)] {
+        &self.frames[index]
+    }
+
+    /// Plays the replay in the terminal.
+    ///
+    /// Space pauses and resumes, `n` and `p` step one tick forward and backward, `[` and `]`
+    /// seek 100 ticks, `g` and `G` jump to the start or the end, and `+` and `-` change the
+    /// number of ticks per frame.
+    pub fn play(&self) -> io::Result<()> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+        let last = self.len() - 1;
+        let mut viewer = Output::new(self.config.board.clone())?;
+        viewer.set_help("q: Quit, space: Pause, n/p: Step, [/]: Seek, g/G: Start/End, +/-: Speed");
+        let mut keys = async_stdin().keys();
+        let mut index = 0;
+        let mut speed = 1;
+        let mut playing = true;
+        loop {
+            for key in &mut keys {
+                match key? {
+                    Key::Char('q') | Key::Esc | Key::Ctrl('c' | 'd') => return Ok(()),
+                    Key::Char(' ') => playing = !playing,
+                    Key::Char('n') => index = (index + 1).min(last),
+                    Key::Char('p') => index = index.saturating_sub(1),
+                    Key::Char(']') => index = (index + 100).min(last),
+                    Key::Char('[') => index = index.saturating_sub(100),
+                    Key::Char('g') => index = 0,
+                    Key::Char('G') => index = last,
+                    Key::Char('+') => speed = (speed * 2).min(1024),
+                    Key::Char('-') => speed = (speed / 2).max(1),
+                    Key::Left | Key::Char('h') => viewer.scroll_left(self.frame(index)),
+                    Key::Down | Key::Char('j') => viewer.scroll_down(self.frame(index)),
+                    Key::Up | Key::Char('k') => viewer.scroll_up(self.frame(index)),
+                    Key::Right | Key::Char('l') => viewer.scroll_right(self.frame(index)),
+                    _ => {}
+                }
+            }
+
+            viewer.draw_players(self.frame(index));
+            viewer.draw_status(&format!(
+                "seed {}, tick {}/{}, {} ticks per frame{}",
+                self.seed,
+                self.tick(index),
+                self.tick(last),
+                speed,
+                if playing { "" } else { " (paused)" }
+            ))?;
+            stdout().flush()?;
+
+            if playing {
+                index = (index + speed).min(last);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io, process};
+
+    use super::{encode_tag, Recorder, Replay};
+    use crate::{config::Config, world::create_simulation};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_round_trip() {
+        let path = env::temp_dir().join(format!("tag-round-trip-{}.replay", process::id()));
+        let config = Config::default();
+        let mut simulation = create_simulation(&config, 7);
+        let mut recorder = Recorder::create(&path, &config, 7, simulation.agents()).unwrap();
+
+        // Record ten ticks, rewind four of them, and record six ticks again
+        let mut expected = vec![simulation.agents().to_vec()];
+        recorder.record(0, simulation.agents()).unwrap();
+        for _ in 0..10 {
+            simulation.update();
+            recorder
+                .record(simulation.tick(), simulation.agents())
+                .unwrap();
+            expected.push(simulation.agents().to_vec());
+        }
+        assert_eq!(simulation.rewind(4), 4);
+        expected.truncate(7);
+        for _ in 0..6 {
+            simulation.update();
+            recorder
+                .record(simulation.tick(), simulation.agents())
+                .unwrap();
+            expected.push(simulation.agents().to_vec());
+        }
+        recorder.flush().unwrap();
+
+        let replay = Replay::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.seed, 7);
+        assert_eq!(replay.len(), 13);
+        for (index, agents) in expected.iter().enumerate() {
+            assert_eq!(replay.tick(index), index as u64);
+            for ((_, recorded), (_, state)) in replay.frame(index).iter().zip(agents) {
+                assert!((recorded.position.x - state.position.x).abs() <= 0.005);
+                assert!((recorded.position.y - state.position.y).abs() <= 0.005);
+                assert_eq!(encode_tag(recorded.tag), encode_tag(state.tag));
+                assert_eq!(recorded.properties, state.properties);
+            }
+        }
+    }
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_skipped_tick() {
+        let path = env::temp_dir().join(format!("tag-skipped-tick-{}.replay", process::id()));
+        let config = Config::default();
+        let simulation = create_simulation(&config, 7);
+        let mut recorder = Recorder::create(&path, &config, 7, simulation.agents()).unwrap();
+        recorder.record(0, simulation.agents()).unwrap();
+        recorder.record(2, simulation.agents()).unwrap();
+        recorder.flush().unwrap();
+
+        let error = Replay::open(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    agent::{AgentState, TagAgent, Team},
+    config::Mode,
+    world::TagWorld,
+};
+
+/// The outcome of a game.
+#[derive(Clone, Debug, Serialize)]
+pub struct MatchResult {
+    /// The number of ticks played
+    pub ticks: u64,
+    pub mode: Mode,
+    /// If the game has ended by its own rules
+    pub finished: bool,
+    /// The score of the red and the blue team in capture the flag
+    pub scores: Option<[u32; 2]>,
+    /// The players from the first to the last place
+    pub ranking: Vec<PlayerResult>,
+}
+
+/// The outcome of a game for a single player.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerResult {
+    pub id: usize,
+    pub team: Option<Team>,
+    /// The number of ticks, the player was "It"
+    pub ticks_as_it: u64,
+    /// The number of players, this player has tagged
+    pub tags: u32,
+    /// The number of times, this player was tagged
+    pub tagged: u32,
+    /// The longest time in ticks, the player was neither "It" nor caught
+    pub longest_evasion: u64,
+    /// The distance, the player has run
+    pub distance: f32,
+    /// One point for every tag made and minus one point for every time tagged
+    pub points: i64,
+}
+
+impl MatchResult {
+    /// Collects the result of the game after `ticks` ticks.
+    ///
+    /// The players are ranked by their points. Ties go to the player, who was "It" for a shorter
+    /// time. In capture the flag, the players of the team with the higher score come first.
+    pub fn new(ticks: u64, world: &TagWorld, agents: &[(TagAgent, AgentState)]) -> Self {
+        let scores = (world.mode == Mode::CaptureTheFlag).then_some(world.scores);
+        let team_score = |team: Option<Team>| {
+            scores
+                .zip(team)
+                .map_or(0, |(scores, team)| scores[team.index()])
+        };
+        let mut ranking = world
+            .stats
+            .iter()
+            .zip(agents)
+            .enumerate()
+            .map(|(id, (stats, (_, state)))| PlayerResult {
+                id,
+                team: state.team,
+                ticks_as_it: stats.ticks_as_it,
+                tags: stats.tags,
+                tagged: stats.tagged,
+                longest_evasion: stats.longest_evasion,
+                distance: stats.distance,
+                points: i64::from(stats.tags) - i64::from(stats.tagged),
+            })
+            .collect::<Vec<_>>();
+        ranking.sort_by(|a, b| {
+            team_score(b.team)
+                .cmp(&team_score(a.team))
+                .then(b.points.cmp(&a.points))
+                .then(a.ticks_as_it.cmp(&b.ticks_as_it))
+                .then(a.id.cmp(&b.id))
+        });
+        Self {
+            ticks,
+            mode: world.mode,
+            finished: world.finished,
+            scores,
+            ranking,
+        }
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Result after {} ticks", self.ticks)?;
+        if !self.finished {
+            write!(f, " (not finished)")?;
+        }
+        writeln!(f)?;
+        if let Some([red, blue]) = self.scores {
+            writeln!(f, "Score: red {}, blue {}", red, blue)?;
+        }
+        writeln!(
+            f,
+            "{:>5} {:>6} {:>5} {:>11} {:>5} {:>7} {:>15} {:>8} {:>6}",
+            "place",
+            "player",
+            "team",
+            "ticks as It",
+            "tags",
+            "tagged",
+            "longest evasion",
+            "distance",
+            "points"
+        )?;
+        for (place, player) in self.ranking.iter().enumerate() {
+            let team = match player.team {
+                Some(Team::Red) => "red",
+                Some(Team::Blue) => "blue",
+                None => "-",
+            };
+            writeln!(
+                f,
+                "{:>5} {:>6} {:>5} {:>11} {:>5} {:>7} {:>15} {:>8.1} {:>6}",
+                place + 1,
+                player.id,
+                team,
+                player.ticks_as_it,
+                player.tags,
+                player.tagged,
+                player.longest_evasion,
+                player.distance,
+                player.points
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,156 @@
+use std::ops::Range;
+
+use rand::{distributions::uniform::SampleUniform, rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
-use tag_game::World;
+use tag_game::{Simulation, TickContext, World};
 
-use crate::agent::{AgentState, Tag, TagAgent};
+use crate::{
+    agent::{AgentState, Position, Properties, Tag, TagAgent, Team, TAG_RADIUS},
+    config::{Config, Mode},
+    map::Grid,
+};
 
 /// Properties of the board of the game.
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Board {
     /// The width of the board.
     pub width: u16,
     /// The height of the board.
     pub height: u16,
+    /// Areas, which can't be entered or seen through.
+    #[serde(default)]
+    pub obstacles: Vec<Area>,
+    /// Safe zones, in which players can't be tagged for a limited time.
+    #[serde(default)]
+    pub bases: Vec<Base>,
+    /// Areas with another terrain than grass. Later areas cover earlier ones.
+    #[serde(default)]
+    pub terrain: Vec<TerrainArea>,
+}
+
+impl Board {
+    /// Returns if the position is on the board.
+    pub fn contains(&self, position: Position) -> bool {
+        (0. ..=self.width as f32 - 1.).contains(&position.x)
+            && (0. ..=self.height as f32 - 1.).contains(&position.y)
+    }
+
+    /// Returns if the position is inside of an obstacle.
+    pub fn is_blocked(&self, position: Position) -> bool {
+        self.obstacles
+            .iter()
+            .any(|obstacle| obstacle.contains(position))
+    }
+
+    /// Returns the terrain at the position.
+    pub fn terrain_at(&self, position: Position) -> Terrain {
+        self.terrain
+            .iter()
+            .rev()
+            .find(|area| area.area.contains(position))
+            .map_or(Terrain::Grass, |area| area.terrain)
+    }
+
+    /// Returns if an agent can run straight from `from` to `to`: No obstacle is in between and
+    /// the terrain on the way is not slower than at both ends.
+    pub fn straight_way(&self, from: Position, to: Position) -> bool {
+        if !self.line_of_sight(from, to) {
+            return false;
+        }
+        if self.terrain.is_empty() {
+            return true;
+        }
+        let speed = self
+            .terrain_at(from)
+            .speed()
+            .min(self.terrain_at(to).speed());
+        Self::samples(from, to).all(|position| self.terrain_at(position).speed() >= speed)
+    }
+
+    /// Returns the positions every quarter of a field on the line between `from` and `to`
+    /// without `from` and `to`.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn samples(from: Position, to: Position) -> impl Iterator<Item = Position> {
+        let steps = (from.distance_squared(to).sqrt() * 4.).ceil() as u32;
+        (1..steps).map(move |step| {
+            let t = step as f32 / steps as f32;
+            Position::new(
+                (to.x - from.x).mul_add(t, from.x),
+                (to.y - from.y).mul_add(t, from.y),
+            )
+        })
+    }
+
+    /// Returns if the position is inside of a base.
+    pub fn in_base(&self, position: Position) -> bool {
+        self.bases.iter().any(|base| base.contains(position))
+    }
+
+    /// Returns if no obstacle is between `from` and `to`.
+    ///
+    /// The line is sampled every quarter of a field, which is fine enough for obstacles of at
+    /// least one field.
+    pub fn line_of_sight(&self, from: Position, to: Position) -> bool {
+        self.obstacles.is_empty()
+            || Self::samples(from, to).all(|position| !self.is_blocked(position))
+    }
+
+    /// Returns the half of the board, which belongs to `team` in capture the flag.
+    pub fn territory(&self, team: Team) -> Area {
+        let half = self.width as f32 / 2.;
+        Area {
+            x: match team {
+                Team::Red => 0.,
+                Team::Blue => half,
+            },
+            y: 0.,
+            width: half,
+            height: self.height as f32,
+        }
+    }
+
+    /// Returns if a player of `team` can start on the board, i.e. the center of a field is not
+    /// blocked. In capture the flag, the field has to be in the territory of the team.
+    pub fn has_free_field(&self, team: Option<Team>) -> bool {
+        let width = f32::from(self.width);
+        let height = f32::from(self.height);
+        (0..self.height).any(|y| {
+            (0..self.width).any(|x| {
+                let center = Position::new(
+                    (f32::from(x) + 0.5).min(width - 1.),
+                    (f32::from(y) + 0.5).min(height - 1.),
+                );
+                team.is_none_or(|team| self.territory(team).contains(center))
+                    && !self.is_blocked(center)
+            })
+        })
+    }
+
+    /// Returns the corner in the territory of `team`, in which caught opponents are jailed.
+    pub fn jail(&self, team: Team) -> Area {
+        let size = 4_f32.min(self.width as f32 / 2.).min(self.height as f32);
+        Area {
+            x: match team {
+                Team::Red => 0.,
+                Team::Blue => self.width as f32 - size,
+            },
+            y: match team {
+                Team::Red => 0.,
+                Team::Blue => self.height as f32 - size,
+            },
+            width: size,
+            height: size,
+        }
+    }
+
+    /// Returns the position of the flag of `team`, while it's not carried.
+    pub fn flag(&self, team: Team) -> Position {
+        let y = (self.height as f32 - 1.) / 2.;
+        match team {
+            Team::Red => Position::new(2_f32.min(self.width as f32 - 1.), y),
+            Team::Blue => Position::new((self.width as f32 - 3.).max(0.), y),
+        }
+    }
 }
 
 impl Default for Board {
@@ -17,26 +158,718 @@ impl Default for Board {
         Self {
             width: 50,
             height: 50,
+            obstacles: Vec::new(),
+            bases: Vec::new(),
+            terrain: Vec::new(),
+        }
+    }
+}
+
+/// A rectangular area on the board.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Area {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Area {
+    /// Returns if the position is inside of the area.
+    pub fn contains(self, position: Position) -> bool {
+        (self.x..self.x + self.width).contains(&position.x)
+            && (self.y..self.y + self.height).contains(&position.y)
+    }
+
+    /// Returns the center of the area.
+    pub fn center(self) -> Position {
+        Position::new(self.x + self.width / 2., self.y + self.height / 2.)
+    }
+}
+
+/// The ground of a field, which changes the speed of the agents on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Terrain {
+    #[default]
+    Grass,
+    Mud,
+    Sand,
+    Track,
+}
+
+impl Terrain {
+    /// Returns the factor, by which the speed of an agent on this terrain is multiplied.
+    pub fn speed(self) -> f32 {
+        match self {
+            Self::Grass => 1.,
+            Self::Mud => 0.5,
+            Self::Sand => 0.75,
+            Self::Track => 1.25,
+        }
+    }
+}
+
+/// An area of the board with another terrain.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainArea {
+    #[serde(flatten)]
+    pub area: Area,
+    pub terrain: Terrain,
+}
+
+/// A safe zone on the board, in which players can't be tagged.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Base {
+    Rectangle(Area),
+    Circle { x: f32, y: f32, radius: f32 },
+}
+
+impl Base {
+    /// Returns if the position is inside of the base.
+    pub fn contains(self, position: Position) -> bool {
+        match self {
+            Self::Rectangle(area) => area.contains(position),
+            Self::Circle { x, y, radius } => {
+                position.distance_squared(Position::new(x, y)) <= radius * radius
+            }
+        }
+    }
+
+    /// Returns the center of the base.
+    pub fn center(self) -> Position {
+        match self {
+            Self::Rectangle(area) => area.center(),
+            Self::Circle { x, y, .. } => Position::new(x, y),
         }
     }
 }
 
+/// The board divided into cells, which remember, when a seeker saw them last, in hide-and-seek.
+#[derive(Clone, Debug)]
+pub struct SearchMap {
+    /// The center of every cell, which is not blocked, and the tick, in which it was seen last
+    cells: Vec<(Position, Option<u64>)>,
+}
+
+impl SearchMap {
+    /// The width and height of a cell.
+    const CELL_SIZE: f32 = 5.;
+
+    pub fn new(board: &Board) -> Self {
+        let cells = (0..board.height)
+            .step_by(Self::CELL_SIZE as usize)
+            .flat_map(|y| {
+                (0..board.width)
+                    .step_by(Self::CELL_SIZE as usize)
+                    .map(move |x| {
+                        Position::new(
+                            (x as f32 + Self::CELL_SIZE / 2.).min(board.width as f32 - 1.),
+                            (y as f32 + Self::CELL_SIZE / 2.).min(board.height as f32 - 1.),
+                        )
+                    })
+            })
+            .filter(|center| !board.is_blocked(*center))
+            .map(|center| (center, None))
+            .collect();
+        Self { cells }
+    }
+
+    /// Marks every cell, whose center is visible, as seen in `tick`.
+    pub fn look(&mut self, tick: u64, visible: impl Fn(Position) -> bool) {
+        for (center, seen) in &mut self.cells {
+            if visible(*center) {
+                *seen = Some(tick);
+            }
+        }
+    }
+
+    /// Returns the center of the cell, which was not seen for the longest time. Of equally old
+    /// cells, the one nearest to `position` is returned.
+    pub fn target(&self, position: Position) -> Option<Position> {
+        self.cells
+            .iter()
+            .min_by(|(a, a_seen), (b, b_seen)| {
+                a_seen.cmp(b_seen).then(
+                    a.distance_squared(position)
+                        .total_cmp(&b.distance_squared(position)),
+                )
+            })
+            .map(|(center, _)| *center)
+    }
+}
+
+/// The flag of a team in capture the flag.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Flag {
+    /// The agent id, which has stolen the flag and carries it
+    pub carrier: Option<usize>,
+}
+
+/// What a player has done in the game so far.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerStats {
+    /// The number of ticks, the player was "It"
+    pub ticks_as_it: u64,
+    /// The number of players, this player has tagged, frozen, infected, found, or caught
+    pub tags: u32,
+    /// The number of times, this player was tagged, frozen, infected, found, or caught
+    pub tagged: u32,
+    /// The number of ticks since the player was "It" or caught the last time
+    pub evasion: u64,
+    /// The longest time in ticks, the player was neither "It" nor caught
+    pub longest_evasion: u64,
+    /// The distance, the player has run
+    pub distance: f32,
+}
+
+/// A player, who is currently "It".
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct It {
+    /// The agent id of the player
+    pub id: usize,
+    /// The agent id, which most recently tagged this player and may not be tagged back
+    pub recent: Option<usize>,
+    /// The agent id, which is chased when hunting cooperatively
+    pub target: Option<usize>,
+}
+
 /// Global state for the game.
+#[derive(Clone)]
 pub struct TagWorld {
     /// The board used in the game
     pub board: Board,
-    /// The current agent id, which is tagged as "It"
-    pub current_it: usize,
-    /// The current agent id, which was recently tagged as "It"
-    pub recent_it: Option<usize>,
+    /// The players, which are currently tagged as "It"
+    pub its: Vec<It>,
+    /// If set, the "It"s split up the other players instead of all chasing the nearest one
+    pub cooperative: bool,
+    /// The rules of the game
+    pub mode: Mode,
+    /// The number of ticks, after which the game ends
+    pub time_limit: Option<u64>,
+    /// The number of tags, after which the game ends
+    pub tag_limit: Option<u32>,
+    /// If the game has ended
+    pub finished: bool,
+    /// The tick, in which every agent became "It" in infection tag
+    pub infected: Vec<Option<u64>>,
+    /// The flags of both teams in capture the flag
+    pub flags: [Flag; 2],
+    /// The score of both teams in capture the flag
+    pub scores: [u32; 2],
+    /// The number of ticks, the seekers count in hide-and-seek
+    pub counting_ticks: u64,
+    /// The distance, in which players see each other in hide-and-seek
+    pub view_distance: f32,
+    /// The areas searched by the seekers in hide-and-seek
+    pub search: SearchMap,
+    /// The statistics of every player
+    pub stats: Vec<PlayerStats>,
+    /// The position of every player after the last update to measure the distance they run
+    pub positions: Vec<Position>,
+    /// The fields of the board to find paths around the obstacles
+    pub grid: Grid,
+    /// The number of ticks, a player is safe in a base
+    pub base_ticks: u64,
+    /// The number of ticks, every agent has spent in a base. It counts down again, while the
+    /// agent is outside of a base.
+    pub dwell: Vec<u64>,
+    /// The context of the current tick
+    pub tick_context: Option<TickContext>,
+}
+
+impl TagWorld {
+    /// Returns the "It", which is played by the agent `id`.
+    pub fn it(&self, id: usize) -> Option<&It> {
+        self.its.iter().find(|it| it.id == id)
+    }
+
+    /// Returns the number of tags in the game so far.
+    pub fn tags(&self) -> u32 {
+        self.stats.iter().map(|stats| stats.tagged).sum()
+    }
+
+    /// Returns if the agent `id` most recently tagged the "It" played by the agent `it`, so it
+    /// can't be tagged back by this "It".
+    pub fn is_recent(&self, it: usize, id: usize) -> bool {
+        self.it(it).is_some_and(|it| it.recent == Some(id))
+    }
+
+    /// Returns if the agent `id` at `position` is in a base and has not used up the time, it
+    /// may stay there.
+    pub fn is_safe(&self, id: usize, position: Position) -> bool {
+        self.dwell[id] < self.base_ticks && self.board.in_base(position)
+    }
+
+    /// Returns the position, to which an agent at `from` runs on the fastest way to `to` around
+    /// the obstacles and through the terrain.
+    pub fn waypoint(&self, from: Position, to: Position) -> Position {
+        self.grid.waypoint(&self.board, from, to)
+    }
+
+    /// Returns if a player at `from` sees the position `to` in hide-and-seek, because it's close
+    /// enough and no obstacle is in between.
+    pub fn can_see(&self, from: Position, to: Position) -> bool {
+        from.distance_squared(to) <= self.view_distance * self.view_distance
+            && self.board.line_of_sight(from, to)
+    }
+
+    /// Returns if none of the `seekers` has the position in its line of sight, no matter how far
+    /// away it is.
+    pub fn is_hidden(&self, position: Position, seekers: &[Position]) -> bool {
+        seekers
+            .iter()
+            .all(|&seeker| !self.board.line_of_sight(seeker, position))
+    }
+
+    /// Returns the nearest place behind an obstacle, which is hidden from all `seekers` and can
+    /// be reached from `position`.
+    pub fn hiding_place(&self, position: Position, seekers: &[Position]) -> Option<Position> {
+        self.grid
+            .hiding_place(position, |place| self.is_hidden(place, seekers))
+    }
+
+    /// Returns if the seekers are still counting in hide-and-seek.
+    pub fn counting(&self) -> bool {
+        self.mode == Mode::HideAndSeek
+            && self
+                .tick_context
+                .is_some_and(|context| context.tick < self.counting_ticks)
+    }
+
+    /// Returns the number of "It"s in every tick up to the latest infection in infection tag.
+    pub fn infection_curve(&self) -> Vec<usize> {
+        let latest = self.infected.iter().flatten().max().copied().unwrap_or(0);
+        (0..=latest)
+            .map(|tick| {
+                self.infected
+                    .iter()
+                    .filter(|infected| infected.is_some_and(|infected| infected <= tick))
+                    .count()
+            })
+            .collect()
+    }
+
+    /// Returns the random generator for the agent in the current tick.
+    ///
+    /// The generator only depends on the seed of the simulation, the tick and the agent, so a
+    /// rewound tick is replayed exactly the same.
+    pub fn rng(&self, id: usize) -> StdRng {
+        let context = self
+            .tick_context
+            .expect("the world is only accessed by agents during a tick");
+        StdRng::seed_from_u64(context.seed_for(id))
+    }
+
+    /// Registers the invariants of the game, which are checked after every tick in debug builds.
+    pub fn add_invariants(simulation: &mut Simulation<TagAgent>) {
+        simulation.add_invariant("\"It\"s are tagged", |world: &Self, agents: &[_]| {
+            let untagged = world
+                .its
+                .iter()
+                .filter(|it| !matches!(agents[it.id].1.tag, Tag::It(_)))
+                .map(|it| it.id)
+                .collect::<Vec<_>>();
+            if untagged.is_empty() {
+                Ok(())
+            } else {
+                Err(untagged)
+            }
+        });
+        simulation.add_invariant("only \"It\"s are tagged", |world: &Self, agents: &[_]| {
+            let its = agents
+                .iter()
+                .enumerate()
+                .filter(|(id, (_, state))| {
+                    matches!(state.tag, Tag::It(_)) && world.it(*id).is_none()
+                })
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            if its.is_empty() {
+                Ok(())
+            } else {
+                Err(its)
+            }
+        });
+        simulation.add_invariant("players are on the board", |world: &Self, agents: &[_]| {
+            let outside = agents
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, state))| !world.board.contains(state.position))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            if outside.is_empty() {
+                Ok(())
+            } else {
+                Err(outside)
+            }
+        });
+    }
+
+    /// Moves caught players into the jail and releases rescued ones. Then, opponents in reach
+    /// steal a flag, and carrying it into the own territory scores. A caught carrier loses the
+    /// flag, which returns to its place.
+    fn capture_the_flag(&mut self, agents: &mut [(TagAgent, AgentState)]) {
+        let board = &self.board;
+        for id in 0..agents.len() {
+            let state = &agents[id].1;
+            if let (Tag::Jailed { rescued }, Some(team)) = (state.tag, state.team) {
+                let jail = board.jail(team.opponent());
+                if !jail.contains(state.position) {
+                    // The nearest opponent in reach has caught the player
+                    let catcher = agents
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, other))| {
+                            other.tag == Tag::None && other.team == Some(team.opponent())
+                        })
+                        .map(|(other, (_, other_state))| {
+                            (other, other_state.position.distance_squared(state.position))
+                        })
+                        .filter(|&(_, distance)| distance <= TAG_RADIUS * TAG_RADIUS)
+                        .min_by(|a, b| a.1.total_cmp(&b.1));
+                    if let Some((catcher, _)) = catcher {
+                        self.stats[catcher].tags += 1;
+                    }
+                    self.stats[id].tagged += 1;
+                    let state = &mut agents[id].1;
+                    state.position = jail.center();
+                    state.tag = Tag::Jailed { rescued: false };
+                } else if rescued {
+                    // The released player returns to the flag of the own team, so it isn't
+                    // caught again right away in the territory of the other team
+                    let state = &mut agents[id].1;
+                    state.position = board.flag(team);
+                    state.tag = Tag::None;
+                }
+            }
+        }
+
+        for team in [Team::Red, Team::Blue] {
+            let flag = &mut self.flags[team.index()];
+            match flag.carrier {
+                Some(carrier) if agents[carrier].1.tag != Tag::None => flag.carrier = None,
+                Some(carrier)
+                    if board
+                        .territory(team.opponent())
+                        .contains(agents[carrier].1.position) =>
+                {
+                    self.scores[team.opponent().index()] += 1;
+                    flag.carrier = None;
+                }
+                Some(_) => {}
+                None => {
+                    let stand = board.flag(team);
+                    flag.carrier = agents
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, state))| {
+                            state.tag == Tag::None && state.team == Some(team.opponent())
+                        })
+                        .map(|(id, (_, state))| (id, state.position.distance_squared(stand)))
+                        .filter(|&(_, distance)| distance <= TAG_RADIUS * TAG_RADIUS)
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(id, _)| id);
+                }
+            }
+        }
+    }
+
+    /// Splits up the taggable players between the "It"s. Repeatedly, the closest pair of an "It"
+    /// without a target and a player, who is not chased yet, is matched.
+    fn assign_targets(&mut self, agents: &[(TagAgent, AgentState)]) {
+        let mut pairs = Vec::new();
+        for (index, it) in self.its.iter().enumerate() {
+            let position = agents[it.id].1.position;
+            for (id, (_, state)) in agents.iter().enumerate() {
+                if state.tag == Tag::None && it.recent != Some(id) {
+                    pairs.push((position.distance_squared(state.position), index, id));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut chased = vec![false; agents.len()];
+        for it in &mut self.its {
+            it.target = None;
+        }
+        for (_, index, id) in pairs {
+            if self.its[index].target.is_none() && !chased[id] {
+                self.its[index].target = Some(id);
+                chased[id] = true;
+            }
+        }
+    }
 }
 
 impl World<TagAgent> for TagWorld {
+    fn begin_tick(&mut self, context: &TickContext) {
+        self.tick_context = Some(*context);
+    }
+
     fn update(&mut self, agents: &mut [(TagAgent, AgentState)]) {
-        // Check, if the current "It" has tagged someone in the latest tick
-        if let Tag::It(Some(next)) = agents[self.current_it].1.tag {
-            self.recent_it = Some(self.current_it);
-            self.current_it = next;
+        if self.finished {
+            return;
+        }
+
+        for ((stats, position), (_, state)) in
+            self.stats.iter_mut().zip(&self.positions).zip(&*agents)
+        {
+            stats.distance += position.distance_squared(state.position).sqrt();
+        }
+
+        // Check, which "It"s have tagged someone in the latest tick. If several "It"s tagged the
+        // same player, the nearest one wins and ties go to the lower agent id.
+        let mut tags = self
+            .its
+            .iter()
+            .enumerate()
+            .filter_map(|(index, it)| match agents[it.id].1.tag {
+                Tag::It(Some(next)) => {
+                    let distance = agents[it.id]
+                        .1
+                        .position
+                        .distance_squared(agents[next].1.position);
+                    Some((next, distance, it.id, index))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.cmp(&b.2)));
+        tags.dedup_by_key(|(next, ..)| *next);
+
+        for it in &self.its {
+            agents[it.id].1.tag = Tag::It(None);
+        }
+        for (_, state) in agents.iter_mut() {
+            if state.tag == (Tag::Frozen { rescued: true }) {
+                state.tag = Tag::None;
+            }
+        }
+        let tick = self.tick_context.map_or(0, |context| context.tick);
+        for (next, _, id, index) in tags {
+            self.stats[id].tags += 1;
+            self.stats[next].tagged += 1;
+            match self.mode {
+                Mode::Classic => {
+                    agents[id].1.tag = Tag::None;
+                    agents[next].1.tag = Tag::It(None);
+                    self.its[index] = It {
+                        id: next,
+                        recent: Some(id),
+                        target: None,
+                    };
+                }
+                Mode::Freeze => agents[next].1.tag = Tag::Frozen { rescued: false },
+                Mode::HideAndSeek => agents[next].1.tag = Tag::Found,
+                Mode::CaptureTheFlag => unreachable!("there is no \"It\" in capture the flag"),
+                Mode::Infection => {
+                    agents[next].1.tag = Tag::It(None);
+                    self.infected[next] = Some(tick + 1);
+                    self.its.push(It {
+                        id: next,
+                        recent: None,
+                        target: None,
+                    });
+                }
+            }
+        }
+
+        if self.mode == Mode::CaptureTheFlag {
+            self.capture_the_flag(agents);
+        }
+        if self.mode == Mode::HideAndSeek && !self.counting() {
+            for it in &self.its {
+                let position = agents[it.id].1.position;
+                let (board, view_distance) = (&self.board, self.view_distance);
+                self.search.look(tick, |center| {
+                    position.distance_squared(center) <= view_distance * view_distance
+                        && board.line_of_sight(position, center)
+                });
+            }
+        }
+        if self.cooperative {
+            self.assign_targets(agents);
+        }
+        for it in &self.its {
+            self.stats[it.id].ticks_as_it += 1;
+        }
+        for ((stats, position), (_, state)) in
+            self.stats.iter_mut().zip(&mut self.positions).zip(&*agents)
+        {
+            if state.tag == Tag::None {
+                stats.evasion += 1;
+                stats.longest_evasion = stats.longest_evasion.max(stats.evasion);
+            } else {
+                stats.evasion = 0;
+            }
+            *position = state.position;
+        }
+        for (dwell, (_, state)) in self.dwell.iter_mut().zip(agents.iter()) {
+            *dwell = if self.board.in_base(state.position) {
+                (*dwell + 1).min(self.base_ticks)
+            } else {
+                dwell.saturating_sub(1)
+            };
+        }
+
+        // The game ends, when the time is up, enough players were tagged, or everyone is caught
+        self.finished = self.time_limit.is_some_and(|limit| tick + 1 >= limit)
+            || self.tag_limit.is_some_and(|limit| self.tags() >= limit)
+            || matches!(
+                self.mode,
+                Mode::Freeze | Mode::Infection | Mode::HideAndSeek
+            ) && agents.iter().all(|(_, state)| state.tag != Tag::None);
+    }
+}
+
+/// Sets up the simulation for the game described by `config`.
+pub fn create_simulation(config: &Config, seed: u64) -> Simulation<TagAgent> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Initialize world
+    // There is no "It" in capture the flag
+    let num_its = if config.mode == Mode::CaptureTheFlag {
+        0
+    } else {
+        config.its
+    };
+    let mut its = Vec::with_capacity(num_its);
+    while its.len() < num_its {
+        let id = rng.gen_range(0..config.num_players);
+        if !its.iter().any(|it: &It| it.id == id) {
+            its.push(It {
+                id,
+                recent: None,
+                target: None,
+            });
+        }
+    }
+    let infected = (0..config.num_players)
+        .map(|id| its.iter().any(|it| it.id == id).then_some(0))
+        .collect();
+    let world = TagWorld {
+        board: config.board.clone(),
+        its,
+        cooperative: config.cooperative,
+        mode: config.mode,
+        time_limit: config.time_limit,
+        tag_limit: config.tag_limit,
+        finished: false,
+        infected,
+        flags: [Flag::default(); 2],
+        scores: [0; 2],
+        counting_ticks: config.counting_ticks,
+        view_distance: config.view_distance,
+        search: SearchMap::new(&config.board),
+        grid: Grid::new(&config.board),
+        stats: vec![PlayerStats::default(); config.num_players],
+        positions: Vec::with_capacity(config.num_players),
+        base_ticks: config.base_ticks,
+        dwell: vec![0; config.num_players],
+        tick_context: None,
+    };
+
+    // create the simulation with the created world
+    let mut simulation = Simulation::new(world);
+    simulation.set_seed(seed);
+    simulation.set_history_capacity(config.history);
+    simulation.set_interaction_radius(Some(TAG_RADIUS));
+    TagWorld::add_invariants(&mut simulation);
+
+    // create the agents
+    // the world already has the information, which agents are "It" at startup
+    for id in 0..config.num_players {
+        let tag = if simulation.world().it(id).is_some() {
+            Tag::It(None)
+        } else {
+            Tag::None
+        };
+        // In capture the flag, the teams alternate and start in their own territory
+        let team = (config.mode == Mode::CaptureTheFlag).then_some(if id % 2 == 0 {
+            Team::Red
+        } else {
+            Team::Blue
+        });
+        // Players start on the board, but not inside of an obstacle. `Config::validate()` ensures,
+        // that there is a free field.
+        let mut sample_position = || {
+            let mut position = Position {
+                x: rng
+                    .gen_range(0. ..config.board.width as f32)
+                    .min(config.board.width as f32 - 1.),
+                y: rng
+                    .gen_range(0. ..config.board.height as f32)
+                    .min(config.board.height as f32 - 1.),
+            };
+            if let Some(team) = team {
+                position.x = config.board.territory(team).x + position.x / 2.;
+            }
+            position
+        };
+        let mut position = sample_position();
+        while config.board.is_blocked(position) {
+            position = sample_position();
+        }
+        simulation.world_mut().positions.push(position);
+        simulation.add_agent(
+            TagAgent,
+            AgentState {
+                tag,
+                team,
+                position,
+                properties: Properties {
+                    untagged_deciding: sample(&mut rng, &config.agents.untagged_deciding),
+                    tagged_deciding: sample(&mut rng, &config.agents.tagged_deciding),
+                    untagged_speed_multiplied: sample(
+                        &mut rng,
+                        &config.agents.untagged_speed_multiplied,
+                    ),
+                    tagged_speed_multiplied: sample(
+                        &mut rng,
+                        &config.agents.tagged_speed_multiplied,
+                    ),
+                },
+            },
+        );
+    }
+    simulation
+}
+
+/// Draws a value from `range`. An empty range like `0.5..0.5` always returns its start, so a
+/// property can be fixed to a single value.
+fn sample<T>(rng: &mut StdRng, range: &Range<T>) -> T
+where
+    T: SampleUniform + PartialOrd + Copy,
+{
+    if range.start < range.end {
+        rng.gen_range(range.clone())
+    } else {
+        range.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_simulation;
+    use crate::{
+        config::{Config, Mode},
+        map,
+    };
+
+    #[test]
+    fn test_spawn_outside_of_obstacles() {
+        // Every other column of the territories is a wall
+        let config = Config {
+            board: map::parse(&"#.#.#.#.\n".repeat(4)).unwrap(),
+            mode: Mode::CaptureTheFlag,
+            num_players: 20,
+            ..Config::default()
+        };
+        let simulation = create_simulation(&config, 3);
+        for (_, state) in simulation.agents() {
+            assert!(!config.board.is_blocked(state.position), "{:?}", state);
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::World;
+use crate::{Wakeup, World};
 
 /// An agent defines, how to act in a simulation.
 ///
@@ -37,4 +37,75 @@ pub trait Agent: Sized + Send + Sync {
         population: &[(Self, Self::State)],
     ) {
     }
+
+    /// Called after [`Agent::on_update()`] to decide, when the agent is updated next.
+    ///
+    /// By default, an agent is updated in every tick. Agents, which have nothing to do for some
+    /// time, may return [`Wakeup::In`] to skip updates or [`Wakeup::Suspend`] to sleep until they
+    /// are woken up by [`Simulation::wake()`].
+    ///
+    /// [`Simulation::wake()`]: crate::Simulation::wake()
+    #[allow(unused_variables)]
+    fn wakeup(&self, id: usize, state: &Self::State, world: &Self::World) -> Wakeup {
+        Wakeup::Next
+    }
+
+    /// Returns the position of the agent on a two-dimensional plane.
+    ///
+    /// The position is used by the interaction phase of the simulation to find agents close to
+    /// each other. Agents without a position never interact. See
+    /// [`Simulation::set_interaction_radius()`].
+    ///
+    /// [`Simulation::set_interaction_radius()`]: crate::Simulation::set_interaction_radius()
+    #[allow(unused_variables)]
+    fn position(&self, state: &Self::State) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// Called once for every pair of agents within the interaction radius.
+    ///
+    /// The method is invoked on the agent with the lower id, so `id` is always smaller than
+    /// `other_id`. Both states may be mutated, which allows symmetric rules like collisions,
+    /// infections or tagging to be decided in one place. Pairs are processed sequentially in
+    /// ascending order of `(id, other_id)`, thus the result is deterministic.
+    #[allow(unused_variables)]
+    fn on_interact(
+        &self,
+        id: usize,
+        state: &mut Self::State,
+        other: &Self,
+        other_id: usize,
+        other_state: &mut Self::State,
+        world: &Self::World,
+    ) {
+    }
+
+    /// Returns, if the agent dies at the end of the tick.
+    ///
+    /// Called for every agent after the world was updated. Dead agents are removed from the
+    /// simulation and passed to [`World::on_death()`]. Removing an agent decreases the ids of all
+    /// agents added after it.
+    #[allow(unused_variables)]
+    fn dies(&self, id: usize, state: &Self::State, world: &Self::World) -> bool {
+        false
+    }
+
+    /// Returns the children of the agent, which are born at the end of the tick.
+    ///
+    /// Called for every agent after the world was updated, including the agents dying in the same
+    /// tick. The state of a child is usually derived from the parent's `state`, optionally with
+    /// some mutation. See [`TickContext::seed_for()`] to mutate deterministically. Children are
+    /// added after the dead agents were removed, in the order of their parents, and are updated
+    /// for the first time in the next tick.
+    ///
+    /// [`TickContext::seed_for()`]: crate::TickContext::seed_for()
+    #[allow(unused_variables)]
+    fn reproduce(
+        &self,
+        id: usize,
+        state: &Self::State,
+        world: &Self::World,
+    ) -> Vec<(Self, Self::State)> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,350 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use rayon::prelude::*;
+
+use crate::{Agent, Simulation};
+
+/// Describes a batch of independent replications of a simulation.
+///
+/// Every replication is created by [`Experiment::create()`], simulated for
+/// [`Experiment::ticks()`] ticks and measured after every tick. The experiment also defines, how
+/// the state of a replication is written to and restored from a checkpoint.
+pub trait Experiment: Sync {
+    /// The agent simulated in every replication.
+    type Agent: Agent + Clone;
+    /// The metrics collected for every replication.
+    type Metrics: Send;
+
+    /// The number of replications.
+    fn replications(&self) -> usize;
+
+    /// The number of ticks simulated in every replication.
+    fn ticks(&self) -> u64;
+
+    /// Creates the simulation and the empty metrics for a replication.
+    ///
+    /// This has to be deterministic, e.g. by seeding the simulation with the replication, so a
+    /// resumed replication yields the same results as an uninterrupted one.
+    fn create(&self, replication: usize) -> (Simulation<Self::Agent>, Self::Metrics);
+
+    /// Updates the metrics after every tick.
+    fn measure(&self, simulation: &Simulation<Self::Agent>, metrics: &mut Self::Metrics);
+
+    /// Writes the state of the simulation and the metrics to a checkpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if writing fails.
+    fn save(
+        &self,
+        simulation: &Simulation<Self::Agent>,
+        metrics: &Self::Metrics,
+        writer: &mut dyn Write,
+    ) -> io::Result<()>;
+
+    /// Restores the state written by [`Experiment::save()`] into a simulation freshly created by
+    /// [`Experiment::create()`] and returns the metrics.
+    ///
+    /// [`Simulation::set_tick()`] may be used to restore the tick.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if reading fails or the checkpoint is invalid.
+    fn load(
+        &self,
+        simulation: &mut Simulation<Self::Agent>,
+        reader: &mut dyn Read,
+    ) -> io::Result<Self::Metrics>;
+}
+
+/// Runs all replications of an [`Experiment`] in parallel and periodically writes checkpoints.
+///
+/// Every replication is checkpointed to its own file in the checkpoint directory. Once a
+/// replication is finished, its checkpoint is marked as done. When the batch is run again with
+/// the same directory, e.g. after it was killed, finished replications are skipped and unfinished
+/// replications are resumed from their latest checkpoint.
+pub struct Batch<'e, E> {
+    experiment: &'e E,
+    directory: PathBuf,
+    checkpoint_every: u64,
+    interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl<'e, E> Batch<'e, E>
+where
+    E: Experiment,
+    <E::Agent as Agent>::State: Clone,
+{
+    /// Creates a batch writing checkpoints to `directory`.
+    pub fn new(experiment: &'e E, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            experiment,
+            directory: directory.into(),
+            checkpoint_every: 1000,
+            interrupt: None,
+        }
+    }
+
+    /// Sets the number of ticks between two checkpoints of a replication. Defaults to 1000.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ticks` is zero.
+    pub fn checkpoint_every(mut self, ticks: u64) -> Self {
+        assert_ne!(ticks, 0, "checkpoint interval must not be zero");
+        self.checkpoint_every = ticks;
+        self
+    }
+
+    /// Sets a flag, which interrupts the batch when set.
+    ///
+    /// Every running replication writes a checkpoint, before [`Batch::run()`] returns.
+    pub fn interrupt_on(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(flag);
+        self
+    }
+
+    fn checkpoint_path(&self, replication: usize, done: bool) -> PathBuf {
+        let extension = if done { "done" } else { "checkpoint" };
+        self.directory
+            .join(format!("replication-{:05}.{}", replication, extension))
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        simulation: &Simulation<E::Agent>,
+        metrics: &E::Metrics,
+    ) -> io::Result<()> {
+        // Write to a temporary file first, so a killed process never leaves a broken checkpoint
+        let temporary = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        self.experiment.save(simulation, metrics, &mut writer)?;
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(temporary, path)
+    }
+
+    fn read(&self, path: &Path, simulation: &mut Simulation<E::Agent>) -> io::Result<E::Metrics> {
+        let mut reader = BufReader::new(File::open(path)?);
+        self.experiment.load(simulation, &mut reader)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    fn replicate(&self, replication: usize) -> io::Result<E::Metrics> {
+        let done = self.checkpoint_path(replication, true);
+        let checkpoint = self.checkpoint_path(replication, false);
+        let (mut simulation, mut metrics) = self.experiment.create(replication);
+        if done.exists() {
+            return self.read(&done, &mut simulation);
+        }
+        if checkpoint.exists() {
+            metrics = self.read(&checkpoint, &mut simulation)?;
+        }
+
+        while simulation.tick() < self.experiment.ticks() {
+            if self.interrupted() {
+                self.write(&checkpoint, &simulation, &metrics)?;
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "batch was interrupted",
+                ));
+            }
+            simulation.update();
+            self.experiment.measure(&simulation, &mut metrics);
+            if simulation.tick() % self.checkpoint_every == 0 {
+                self.write(&checkpoint, &simulation, &metrics)?;
+            }
+        }
+
+        self.write(&done, &simulation, &metrics)?;
+        if checkpoint.exists() {
+            fs::remove_file(checkpoint)?;
+        }
+        Ok(metrics)
+    }
+
+    /// Runs all unfinished replications and returns the metrics of all replications in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, if a checkpoint could not be written or read. If the batch was
+    /// interrupted, an error of kind [`io::ErrorKind::Interrupted`] is returned.
+    pub fn run(&self) -> io::Result<Vec<E::Metrics>> {
+        fs::create_dir_all(&self.directory)?;
+        (0..self.experiment.replications())
+            .into_par_iter()
+            .map(|replication| self.replicate(replication))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, BufRead, BufReader, Read, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{Agent, Batch, Experiment, Simulation, TickContext, World};
+
+    #[derive(Clone, Default)]
+    struct SeededWorld(Option<TickContext>);
+
+    #[derive(Clone)]
+    struct Walker;
+    impl Agent for Walker {
+        type State = u64;
+        type World = SeededWorld;
+
+        fn on_update(
+            &self,
+            id: usize,
+            state: &mut Self::State,
+            world: &Self::World,
+            _population: &[(Self, Self::State)],
+        ) {
+            *state += world.0.unwrap().seed_for(id) % 10;
+        }
+    }
+    impl World<Walker> for SeededWorld {
+        fn begin_tick(&mut self, context: &TickContext) {
+            self.0 = Some(*context);
+        }
+    }
+
+    /// Sums up the states of all walkers after every tick. Interrupts the batch in the
+    /// specified tick.
+    struct Walk {
+        interrupt: Option<(u64, Arc<AtomicBool>)>,
+    }
+
+    impl Experiment for Walk {
+        type Agent = Walker;
+        type Metrics = Vec<u64>;
+
+        fn replications(&self) -> usize {
+            4
+        }
+
+        fn ticks(&self) -> u64 {
+            25
+        }
+
+        fn create(&self, replication: usize) -> (Simulation<Walker>, Vec<u64>) {
+            let mut simulation = Simulation::new(SeededWorld::default());
+            simulation.set_seed(replication as u64);
+            for _ in 0..3 {
+                simulation.add_agent(Walker, 0);
+            }
+            (simulation, Vec::new())
+        }
+
+        fn measure(&self, simulation: &Simulation<Walker>, metrics: &mut Vec<u64>) {
+            metrics.push(simulation.agents().iter().map(|(_, state)| state).sum());
+            if let Some((tick, flag)) = &self.interrupt {
+                if simulation.tick() == *tick {
+                    flag.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        fn save(
+            &self,
+            simulation: &Simulation<Walker>,
+            metrics: &Vec<u64>,
+            writer: &mut dyn Write,
+        ) -> io::Result<()> {
+            let join = |values: &mut dyn Iterator<Item = &u64>| {
+                values.map(u64::to_string).collect::<Vec<_>>().join(" ")
+            };
+            writeln!(writer, "{}", simulation.tick())?;
+            writeln!(
+                writer,
+                "{}",
+                join(&mut simulation.agents().iter().map(|(_, state)| state))
+            )?;
+            writeln!(writer, "{}", join(&mut metrics.iter()))
+        }
+
+        fn load(
+            &self,
+            simulation: &mut Simulation<Walker>,
+            reader: &mut dyn Read,
+        ) -> io::Result<Vec<u64>> {
+            let lines = BufReader::new(reader)
+                .lines()
+                .map(|line| {
+                    Ok(line?
+                        .split_whitespace()
+                        .map(|value| value.parse().unwrap())
+                        .collect::<Vec<u64>>())
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            simulation.set_tick(lines[0][0]);
+            for ((_, state), value) in simulation.agents_mut().iter_mut().zip(&lines[1]) {
+                *state = *value;
+            }
+            Ok(lines[2].clone())
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_resume() {
+        let directory = std::env::temp_dir().join(format!("tag_game_batch_{}", std::process::id()));
+        let uninterrupted = Walk { interrupt: None };
+        let expected = Batch::new(&uninterrupted, directory.join("uninterrupted"))
+            .checkpoint_every(10)
+            .run()
+            .unwrap();
+        assert_eq!(expected.len(), 4);
+        assert!(expected.iter().all(|metrics| metrics.len() == 25));
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let interrupted = Walk {
+            interrupt: Some((13, Arc::clone(&flag))),
+        };
+        let batch = Batch::new(&interrupted, directory.join("interrupted"))
+            .checkpoint_every(10)
+            .interrupt_on(Arc::clone(&flag));
+        let error = batch.run().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+
+        flag.store(false, Ordering::Relaxed);
+        let resumed = Walk { interrupt: None };
+        let results = Batch::new(&resumed, directory.join("interrupted"))
+            .checkpoint_every(10)
+            .run()
+            .unwrap();
+        assert_eq!(results, expected);
+
+        // Finished replications are not simulated again
+        let results = Batch::new(&interrupted, directory.join("interrupted"))
+            .interrupt_on(Arc::new(AtomicBool::new(true)))
+            .run()
+            .unwrap();
+        assert_eq!(results, expected);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use std::{cmp::Ordering, ops::Range};
+
+use rayon::prelude::*;
+
+use crate::{
+    schedule::{mix, SplitMix64},
+    sensitivity::Factor,
+};
+
+/// The method used by a [`Calibration`] to search the parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// Approximate Bayesian computation by rejection.
+    ///
+    /// `samples` parameter sets are drawn uniformly from the factor ranges, and the `accepted`
+    /// ones closest to the targets are kept as samples of the posterior distribution.
+    Rejection { samples: usize, accepted: usize },
+    /// A simple (μ + λ) evolution strategy.
+    ///
+    /// The first generation of `population` parameter sets is drawn uniformly from the factor
+    /// ranges. In every following generation, the better half survives, and the other half is
+    /// replaced by mutated copies of the survivors. The mutations shrink over the generations.
+    /// The survivors are run again with a new seed, so a parameter set, which came close to the
+    /// targets by chance, does not stay on top.
+    Evolution {
+        population: usize,
+        generations: usize,
+    },
+}
+
+/// An observed summary statistic, which the simulation should reproduce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    /// The name of the statistic.
+    pub name: String,
+    /// The observed value.
+    pub value: f64,
+    /// The difference to the observed value, which counts as a distance of one.
+    pub scale: f64,
+}
+
+/// A parameter set with the statistics simulated with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// One value per factor in the order the factors were added.
+    pub parameters: Vec<f64>,
+    /// One value per target in the order the targets were added.
+    pub statistics: Vec<f64>,
+    /// The distance of the statistics to the targets.
+    pub distance: f64,
+}
+
+/// The outcome of a [`Calibration`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    /// The accepted posterior samples or the final generation, closest to the targets first.
+    pub candidates: Vec<Candidate>,
+    /// The number of times the model was run.
+    pub evaluations: usize,
+}
+
+impl Fit {
+    /// Returns the candidate closest to the targets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no candidates.
+    pub fn best(&self) -> &Candidate {
+        &self.candidates[0]
+    }
+
+    /// Returns the mean of every parameter over all candidates.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean(&self) -> Vec<f64> {
+        let count = self.candidates.len().max(1) as f64;
+        let factors = self.candidates.first().map_or(0, |c| c.parameters.len());
+        (0..factors)
+            .map(|factor| {
+                self.candidates
+                    .iter()
+                    .map(|candidate| candidate.parameters[factor])
+                    .sum::<f64>()
+                    / count
+            })
+            .collect()
+    }
+}
+
+/// Fits the parameters of a model to observed summary statistics.
+///
+/// The model is run repeatedly with candidate parameters from the factor ranges. Its statistics
+/// are compared to the [`Target`]s by the Euclidean distance of the differences divided by their
+/// scale.
+///
+/// ```
+/// use tag_game::{Calibration, Method};
+///
+/// let fit = Calibration::new(Method::Evolution {
+///     population: 20,
+///     generations: 30,
+/// })
+/// .factor("x", 0.0..10.0)
+/// .target("double x", 7.0)
+/// .run(|parameters, _seed| vec![2. * parameters[0]]);
+///
+/// assert!((fit.best().parameters[0] - 3.5).abs() < 0.05);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    method: Method,
+    factors: Vec<Factor>,
+    targets: Vec<Target>,
+    seed: u64,
+}
+
+impl Calibration {
+    /// Creates a calibration without factors and targets.
+    pub fn new(method: Method) -> Self {
+        Self {
+            method,
+            factors: Vec::new(),
+            targets: Vec::new(),
+            seed: 0,
+        }
+    }
+
+    /// Adds a parameter with the range of its possible values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn factor(mut self, name: impl Into<String>, range: Range<f64>) -> Self {
+        assert!(
+            range.start < range.end,
+            "the range of a factor must not be empty"
+        );
+        self.factors.push(Factor {
+            name: name.into(),
+            range,
+        });
+        self
+    }
+
+    /// Adds a target, whose differences are measured relative to `value`.
+    ///
+    /// If `value` is zero, differences are measured absolutely.
+    pub fn target(self, name: impl Into<String>, value: f64) -> Self {
+        let scale = if value == 0. { 1. } else { value.abs() };
+        self.scaled_target(name, value, scale)
+    }
+
+    /// Adds a target, whose differences are divided by `scale`.
+    pub fn scaled_target(mut self, name: impl Into<String>, value: f64, scale: f64) -> Self {
+        self.targets.push(Target {
+            name: name.into(),
+            value,
+            scale,
+        });
+        self
+    }
+
+    /// Sets the seed of the search.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Returns the factors in the order they were added.
+    pub fn factors(&self) -> &[Factor] {
+        &self.factors
+    }
+
+    /// Returns the targets in the order they were added.
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    /// Returns the distance of `statistics` to the targets.
+    ///
+    /// Undefined statistics result in an infinite distance.
+    pub fn distance(&self, statistics: &[f64]) -> f64 {
+        let distance = self
+            .targets
+            .iter()
+            .zip(statistics)
+            .map(|(target, value)| ((value - target.value) / target.scale).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        if distance.is_nan() {
+            f64::INFINITY
+        } else {
+            distance
+        }
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> Vec<f64> {
+        self.factors
+            .iter()
+            .map(|f| f.range.start + rng.uniform() * (f.range.end - f.range.start))
+            .collect()
+    }
+
+    /// Runs the model for every parameter set in parallel.
+    fn evaluate<F>(&self, model: &F, first: usize, parameters: Vec<Vec<f64>>) -> Vec<Candidate>
+    where
+        F: Fn(&[f64], u64) -> Vec<f64> + Sync,
+    {
+        parameters
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, parameters)| {
+                let statistics = model(&parameters, mix(self.seed ^ (first + index) as u64));
+                assert_eq!(
+                    statistics.len(),
+                    self.targets.len(),
+                    "number of statistics does not match the number of targets"
+                );
+                Candidate {
+                    distance: self.distance(&statistics),
+                    parameters,
+                    statistics,
+                }
+            })
+            .collect()
+    }
+
+    /// Searches the parameters, which reproduce the targets best.
+    ///
+    /// `model` is called with one value per factor and a seed, which differs for every call, and
+    /// returns one statistic per target. For a stochastic model, averaging the statistics over a
+    /// few replications makes the search more reliable.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn run<F>(&self, model: F) -> Fit
+    where
+        F: Fn(&[f64], u64) -> Vec<f64> + Sync,
+    {
+        let mut rng = SplitMix64(self.seed);
+        let by_distance = |a: &Candidate, b: &Candidate| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+        };
+        match self.method {
+            Method::Rejection { samples, accepted } => {
+                let parameters = (0..samples).map(|_| self.sample(&mut rng)).collect();
+                let mut candidates = self.evaluate(&model, 0, parameters);
+                candidates.sort_by(by_distance);
+                candidates.truncate(accepted);
+                Fit {
+                    candidates,
+                    evaluations: samples,
+                }
+            }
+            Method::Evolution {
+                population,
+                generations,
+            } => {
+                let parameters = (0..population).map(|_| self.sample(&mut rng)).collect();
+                let mut candidates = self.evaluate(&model, 0, parameters);
+                let mut evaluations = population;
+                let survivors = population.div_ceil(2);
+                for generation in 1..generations {
+                    candidates.sort_by(by_distance);
+                    candidates.truncate(survivors);
+                    // The mutation shrinks from a fifth to a hundredth of the range
+                    let progress = generation as f64 / generations as f64;
+                    let strength = 0.2 * 0.05_f64.powf(progress);
+                    let children = (survivors..population)
+                        .map(|_| {
+                            let parent = &candidates[rng.below(candidates.len())].parameters;
+                            parent
+                                .iter()
+                                .zip(&self.factors)
+                                .map(|(value, factor)| {
+                                    let range = &factor.range;
+                                    let width = range.end - range.start;
+                                    (value + rng.normal() * strength * width)
+                                        .clamp(range.start, range.end)
+                                })
+                                .collect()
+                        })
+                        .collect::<Vec<_>>();
+                    let parameters = candidates
+                        .drain(..)
+                        .map(|candidate| candidate.parameters)
+                        .chain(children)
+                        .collect();
+                    candidates = self.evaluate(&model, evaluations, parameters);
+                    evaluations += population;
+                }
+                candidates.sort_by(by_distance);
+                Fit {
+                    candidates,
+                    evaluations,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calibration, Method};
+    use crate::schedule::SplitMix64;
+
+    /// A noisy model with the statistics `x + y` and `x * y`.
+    fn model(parameters: &[f64], seed: u64) -> Vec<f64> {
+        let noise = SplitMix64(seed).normal() * 0.01;
+        let (x, y) = (parameters[0], parameters[1]);
+        vec![x + y + noise, x * y + noise]
+    }
+
+    fn calibration(method: Method) -> Calibration {
+        // `x + y = 3` and `x * y = 2` has the solutions `(1, 2)` and `(2, 1)`
+        Calibration::new(method)
+            .factor("x", 0.0..1.5)
+            .factor("y", 0.0..4.0)
+            .target("sum", 3.)
+            .target("product", 2.)
+            .seed(5)
+    }
+
+    #[test]
+    fn test_distance() {
+        let calibration = Calibration::new(Method::Rejection {
+            samples: 1,
+            accepted: 1,
+        })
+        .target("a", 10.)
+        .target("b", 0.)
+        .scaled_target("c", 1., 0.5);
+        assert_eq!(calibration.distance(&[10., 3., 3.]), 5.);
+        assert_eq!(calibration.distance(&[10., 0., 2.]), 2.);
+        assert!(calibration.distance(&[f64::NAN, 0., 0.]).is_infinite());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_rejection() {
+        let fit = calibration(Method::Rejection {
+            samples: 4000,
+            accepted: 40,
+        })
+        .run(model);
+        assert_eq!(fit.evaluations, 4000);
+        assert_eq!(fit.candidates.len(), 40);
+        assert!(fit
+            .candidates
+            .windows(2)
+            .all(|pair| pair[0].distance <= pair[1].distance));
+        let mean = fit.mean();
+        assert!((mean[0] - 1.).abs() < 0.1, "{:?}", mean);
+        assert!((mean[1] - 2.).abs() < 0.2, "{:?}", mean);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_evolution() {
+        let calibration = calibration(Method::Evolution {
+            population: 30,
+            generations: 40,
+        });
+        let fit = calibration.run(model);
+        assert_eq!(fit.evaluations, 40 * 30);
+        assert_eq!(fit.candidates.len(), 30);
+        let best = fit.best();
+        assert!(best.distance < 0.02, "{:?}", best);
+        assert!((best.parameters[0] - 1.).abs() < 0.05, "{:?}", best);
+        assert!((best.parameters[1] - 2.).abs() < 0.05, "{:?}", best);
+        assert_eq!(fit, calibration.run(model));
+    }
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_evolution_lucky_runs() {
+        // Every tenth run hits the target regardless of the parameter
+        let model = |parameters: &[f64], seed: u64| {
+            if SplitMix64(seed).below(10) == 0 {
+                vec![0.]
+            } else {
+                vec![parameters[0]]
+            }
+        };
+        let fit = Calibration::new(Method::Evolution {
+            population: 20,
+            generations: 30,
+        })
+        .factor("x", 0.0..1.0)
+        .scaled_target("x", 0., 0.1)
+        .seed(3)
+        .run(model);
+        assert!(fit.mean()[0] < 0.05, "{:?}", fit.mean());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+use crate::{schedule::Scheduler, Agent, Population, Simulation};
+
+/// A copy of the state of a [`Simulation`] at the beginning of a tick.
+///
+/// Snapshots are created with [`Simulation::snapshot()`] and may be restored with
+/// [`Simulation::restore()`].
+pub struct Snapshot<A: Agent> {
+    pub(crate) tick: u64,
+    pub(crate) world: A::World,
+    pub(crate) agents: Vec<(A, A::State)>,
+    pub(crate) scheduler: Scheduler<A>,
+    pub(crate) population: Population,
+}
+
+impl<A: Agent> Snapshot<A> {
+    /// Returns the tick, which is simulated next from this snapshot.
+    #[inline]
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Returns the global state.
+    #[inline]
+    pub fn world(&self) -> &A::World {
+        &self.world
+    }
+
+    /// Returns all agents and their states.
+    #[inline]
+    pub fn agents(&self) -> &[(A, A::State)] {
+        &self.agents
+    }
+
+    /// Returns the births and deaths up to this snapshot.
+    #[inline]
+    pub fn population(&self) -> Population {
+        self.population
+    }
+}
+
+impl<A> Clone for Snapshot<A>
+where
+    A: Agent + Clone,
+    A::State: Clone,
+    A::World: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tick: self.tick,
+            world: self.world.clone(),
+            agents: self.agents.clone(),
+            scheduler: self.scheduler.clone(),
+            population: self.population,
+        }
+    }
+}
+
+/// Creates a snapshot of a simulation.
+pub(crate) type Recorder<A> = fn(&Simulation<A>) -> Snapshot<A>;
+
+/// A ring buffer of the latest snapshots of a simulation.
+pub(crate) struct History<A: Agent> {
+    snapshots: VecDeque<Snapshot<A>>,
+    capacity: usize,
+    /// Creates a snapshot. This is only set, if the world can be cloned.
+    pub(crate) recorder: Option<Recorder<A>>,
+    /// The number of ticks, which were rewound and not replayed yet.
+    pub(crate) rewound: u64,
+}
+
+impl<A: Agent> History<A> {
+    pub(crate) fn new() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity: 0,
+            recorder: None,
+            rewound: 0,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.snapshots.len() > capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub(crate) fn push(&mut self, snapshot: Snapshot<A>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Removes the latest `ticks` snapshots and returns the oldest removed one.
+    pub(crate) fn pop(&mut self, ticks: usize) -> Option<Snapshot<A>> {
+        let ticks = ticks.min(self.snapshots.len());
+        self.snapshots.drain(self.snapshots.len() - ticks..).next()
+    }
+}
@@ -0,0 +1,36 @@
+use std::{error::Error, fmt};
+
+use crate::Agent;
+
+/// A check, which has to hold after every tick.
+pub(crate) struct Invariant<A: Agent> {
+    pub(crate) name: String,
+    #[allow(clippy::type_complexity)]
+    pub(crate) check:
+        Box<dyn Fn(&A::World, &[(A, A::State)]) -> Result<(), Vec<usize>> + Send + Sync>,
+}
+
+/// Returned by [`Simulation::check_invariants()`], when an invariant does not hold.
+///
+/// [`Simulation::check_invariants()`]: crate::Simulation::check_invariants()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// The number of ticks simulated, when the violation was detected.
+    pub tick: u64,
+    /// The name of the violated invariant.
+    pub name: String,
+    /// The ids of the offending agents. This may be empty, if the invariant concerns the world.
+    pub agents: Vec<usize>,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invariant `{}` violated at tick {} by agents {:?}",
+            self.name, self.tick, self.agents
+        )
+    }
+}
+
+impl Error for InvariantViolation {}
@@ -24,6 +24,56 @@
 //! When all agents are updated, the world state is updated via [`World::update()`]. Unlike the agents,
 //! the [`World`] can mutate all states, including the global one.
 //!
+//! Optionally, agents close to each other can interact. When an interaction radius is set with
+//! [`Simulation::set_interaction_radius()`], every pair of agents, whose [`Agent::position()`] is
+//! within this radius, is passed exactly once to [`Agent::on_interact()`] and
+//! [`World::interact()`]. This is useful for symmetric rules like collisions or tagging.
+//!
+//! Agents don't need to be updated in every tick. After being updated, [`Agent::wakeup()`] decides,
+//! when the agent is updated next, so agents without anything to do can sleep for some ticks.
+//! [`Event`]s can be scheduled for future ticks with [`Simulation::schedule()`]. For sparse models,
+//! [`Simulation::skip_to_next_event()`] jumps straight to the next tick, in which anything happens.
+//! Agents and the world may also be updated at different rates by assigning them a [`Period`].
+//! Before every tick, [`World::begin_tick()`] receives a [`TickContext`] describing the tick.
+//!
+//! Agents may also die and reproduce. After every tick, agents for which [`Agent::dies()`] returns
+//! `true` are removed, and the children returned by [`Agent::reproduce()`] are added. The world is
+//! notified about every death with [`World::on_death()`]. [`Simulation::age()`] returns the number
+//! of ticks an agent has lived, and [`Simulation::population()`] counts all births and deaths as
+//! a [`Population`].
+//!
+//! To catch bugs in a model early, invariants can be registered with
+//! [`Simulation::add_invariant()`]. In debug builds, they are checked after every tick.
+//!
+//! The simulation can keep the latest ticks in a history by setting
+//! [`Simulation::set_history_capacity()`]. [`Simulation::rewind()`] restores an earlier tick and
+//! [`Simulation::replay()`] simulates the rewound ticks again. As long as agents draw their random
+//! numbers from a generator seeded with [`TickContext::seed_for()`], the replayed ticks are
+//! identical to the original ones.
+//!
+//! A [`SimulationRunner`] moves a simulation to a background thread. It is controlled by
+//! [`Command`]s and publishes a [`Snapshot`] after every tick, so rendering does not block the
+//! simulation.
+//!
+//! Experiments
+//! -----------
+//!
+//! Many replications of a simulation can be run in parallel as a [`Batch`]. The replications are
+//! described by an [`Experiment`], which also defines, how to write and read checkpoints. The
+//! batch periodically writes checkpoints, so an interrupted batch resumes, where it stopped.
+//!
+//! Instead of guessing the number of replications, a [`ReplicationController`] runs replications,
+//! until the confidence interval of every chosen metric is precise enough, and returns a
+//! [`Report`] with the final estimates.
+//!
+//! To find out, which inputs drive the outcome of a simulation, a [`SensitivityAnalysis`] samples
+//! the inputs with a [`Design`], like a Latin hypercube, a Sobol' sequence, or Morris' screening,
+//! and ranks them by their sensitivity indices.
+//!
+//! A [`Calibration`] fits the inputs to observed summary statistics. It repeatedly runs the
+//! simulation with candidate inputs and returns a [`Fit`] with posterior samples from approximate
+//! Bayesian computation or the final generation of an evolution strategy, closest first.
+//!
 //! Examples
 //! --------
 //!
@@ -120,11 +170,53 @@
 //! # #[cfg(not(miri))]
 //! simulation.update();
 //! ```
+//!
+//! Instead of calling [`Simulation::update()`] in a loop, the ticks may also be consumed as an
+//! iterator. [`Simulation::ticks()`] yields a [`Snapshot`] after every tick, while
+//! [`Simulation::observe()`] maps the simulation to any value without cloning it:
+//!
+//! ```
+//! # use tag_game::{Simulation, Agent, World};
+//! # #[derive(Clone)] struct MyAgent;
+//! # impl Agent for MyAgent { type State = (); type World = (); }
+//! # let mut simulation = Simulation::new(());
+//! # simulation.add_agent(MyAgent, ());
+//! # #[cfg(not(miri))]
+//! let ticks = simulation
+//!     .observe(|simulation| simulation.tick())
+//!     .every(10)
+//!     .until(|tick| *tick >= 30)
+//!     .collect::<Vec<_>>();
+//! # #[cfg(not(miri))]
+//! assert_eq!(ticks, [10, 20, 30]);
+//! ```
 
 mod agent;
+mod batch;
+mod calibration;
+mod history;
+mod invariant;
+mod population;
+mod replication;
+mod runner;
+mod schedule;
+mod sensitivity;
 mod simulation;
+mod spatial;
+mod ticks;
 mod world;
 
 pub use self::agent::Agent;
+pub use self::batch::{Batch, Experiment};
+pub use self::calibration::{Calibration, Candidate, Fit, Method, Target};
+pub use self::history::Snapshot;
+pub use self::invariant::InvariantViolation;
+pub use self::population::Population;
+pub use self::replication::{Estimate, ReplicationController, Report, RunningStats};
+pub use self::runner::{Command, SimulationRunner};
+pub use self::schedule::{Event, Period, TickContext, Wakeup};
+pub use self::sensitivity::{Design, Factor, FactorSensitivity, Indices, SensitivityAnalysis};
 pub use self::simulation::Simulation;
+pub use self::spatial::SpatialGrid;
+pub use self::ticks::{Ticks, Until};
 pub use self::world::World;
@@ -0,0 +1,18 @@
+/// Counts the births and deaths in a [`Simulation`].
+///
+/// The number of agents currently alive is the length of [`Simulation::agents()`].
+///
+/// [`Simulation`]: crate::Simulation
+/// [`Simulation::agents()`]: crate::Simulation::agents()
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Population {
+    /// The number of children spawned by [`Agent::reproduce()`].
+    ///
+    /// [`Agent::reproduce()`]: crate::Agent::reproduce()
+    pub births: u64,
+    /// The number of agents removed by [`Agent::dies()`] or [`Simulation::remove_agent()`].
+    ///
+    /// [`Agent::dies()`]: crate::Agent::dies()
+    /// [`Simulation::remove_agent()`]: crate::Simulation::remove_agent()
+    pub deaths: u64,
+}
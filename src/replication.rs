@@ -0,0 +1,506 @@
+use std::{f64::consts::PI, fmt};
+
+use rayon::prelude::*;
+
+/// Tracks the mean and the variance of a sequence of values.
+///
+/// The values are not stored. The running variance is computed with Welford's algorithm, which
+/// is numerically stable.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    /// Creates statistics without any value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.count as f64;
+        self.mean += delta / count;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Returns the number of added values.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the mean of all values.
+    #[inline]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the sample variance of all values.
+    ///
+    /// The variance is zero for less than two values.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+
+    /// Returns the sample standard deviation of all values.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the half width of the confidence interval of the mean.
+    ///
+    /// The interval is based on Student's t-distribution. For less than two values, the half
+    /// width is infinite.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `confidence` is not between 0 and 1.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn half_width(&self, confidence: f64) -> f64 {
+        assert!(
+            confidence > 0. && confidence < 1.,
+            "confidence must be between 0 and 1"
+        );
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let t = student_t_quantile(1. - (1. - confidence) / 2., (self.count - 1) as f64);
+        t * self.std_dev() / (self.count as f64).sqrt()
+    }
+}
+
+/// Approximates the quantile function of the standard normal distribution.
+///
+/// Uses the rational approximation by Peter J. Acklam with a relative error below 1.15e-9.
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const LOW: f64 = 0.024_25;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.)
+    };
+    if p < LOW {
+        tail((-2. * p.ln()).sqrt())
+    } else if p > 1. - LOW {
+        -tail((-2. * (1. - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.)
+    }
+}
+
+/// Approximates the quantile function of Student's t-distribution with `df` degrees of freedom.
+///
+/// Uses the Cornish-Fisher expansion around the normal quantile, which is accurate to a few
+/// parts per thousand for four or more degrees of freedom. The expansion is far off for fewer
+/// degrees of freedom, so the exact quantiles are used for one and two degrees of freedom, and
+/// the expansion is refined with Newton's method on the exact distribution function for three.
+fn student_t_quantile(p: f64, df: f64) -> f64 {
+    if df < 1.5 {
+        return (PI * (p - 0.5)).tan();
+    }
+    if df < 2.5 {
+        return (2. * p - 1.) / (2. * p * (1. - p)).sqrt();
+    }
+    let z = normal_quantile(p);
+    let z3 = z.powi(3);
+    let z5 = z.powi(5);
+    let z7 = z.powi(7);
+    let mut t = z
+        + (z3 + z) / (4. * df)
+        + (5. * z5 + 16. * z3 + 3. * z) / (96. * df.powi(2))
+        + (3. * z7 + 19. * z5 + 17. * z3 - 15. * z) / (384. * df.powi(3));
+    if df < 3.5 {
+        let sqrt_3 = 3_f64.sqrt();
+        for _ in 0..4 {
+            let cdf = 0.5 + (sqrt_3 * t / (3. + t * t) + (t / sqrt_3).atan()) / PI;
+            let pdf = 6. * sqrt_3 / (PI * (3. + t * t).powi(2));
+            t -= (cdf - p) / pdf;
+        }
+    }
+    t
+}
+
+/// The estimate of a metric over all replications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// The name of the metric.
+    pub name: String,
+    /// The statistics of the metric over all replications.
+    pub stats: RunningStats,
+    /// The half width of the confidence interval of the mean.
+    pub half_width: f64,
+}
+
+impl Estimate {
+    /// Returns the mean of the metric.
+    pub fn mean(&self) -> f64 {
+        self.stats.mean()
+    }
+
+    /// Returns the lower and the upper bound of the confidence interval.
+    pub fn interval(&self) -> (f64, f64) {
+        (self.mean() - self.half_width, self.mean() + self.half_width)
+    }
+
+    /// Returns the half width of the confidence interval relative to the mean.
+    ///
+    /// This is zero, if the half width is zero, even if the mean is zero as well, and infinite,
+    /// if only the mean is zero.
+    pub fn relative_half_width(&self) -> f64 {
+        if self.half_width == 0. {
+            0.
+        } else {
+            self.half_width / self.mean().abs()
+        }
+    }
+}
+
+/// The final estimates of a [`ReplicationController`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The estimates of all metrics in the order they were specified.
+    pub estimates: Vec<Estimate>,
+    /// The number of replications run.
+    pub replications: usize,
+    /// The confidence level of the intervals.
+    pub confidence: f64,
+    /// If the target precision was reached for all metrics.
+    pub converged: bool,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} replications ({}converged), {}% confidence intervals:",
+            self.replications,
+            if self.converged { "" } else { "not " },
+            self.confidence * 100.
+        )?;
+        for estimate in &self.estimates {
+            let (lower, upper) = estimate.interval();
+            writeln!(
+                f,
+                "  {}: {:.4} ± {:.4} ({:.2}%) [{:.4}, {:.4}]",
+                estimate.name,
+                estimate.mean(),
+                estimate.half_width,
+                estimate.relative_half_width() * 100.,
+                lower,
+                upper
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs replications until the means of all metrics are estimated precisely enough.
+///
+/// Replications are run in parallel in rounds of [`ReplicationController::batch_size()`]. After
+/// every round, the confidence interval of every metric is checked. The controller stops, when
+/// the half width of every interval is within the relative precision of its mean or within the
+/// absolute precision, or when the maximum number of replications is reached. A metric with a
+/// mean of zero can only meet the relative precision, if all of its values are the same, so an
+/// absolute precision should be set for metrics, which may average to zero.
+///
+/// ```
+/// use tag_game::ReplicationController;
+///
+/// let report = ReplicationController::new(vec!["value"])
+///     .confidence(0.95)
+///     .relative_precision(0.02)
+///     .run(|replication| vec![100. + (replication % 7) as f64]);
+///
+/// assert!(report.converged);
+/// assert!(report.estimates[0].relative_half_width() <= 0.02);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplicationController {
+    metrics: Vec<String>,
+    confidence: f64,
+    relative_precision: f64,
+    absolute_precision: f64,
+    min_replications: usize,
+    max_replications: usize,
+    batch_size: usize,
+}
+
+impl ReplicationController {
+    /// Creates a controller for the metrics with the given names.
+    ///
+    /// By default, replications are run until the 95% confidence interval is within ±2% of the
+    /// mean, with at least 10 and at most 1000 replications.
+    pub fn new(metrics: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            metrics: metrics.into_iter().map(Into::into).collect(),
+            confidence: 0.95,
+            relative_precision: 0.02,
+            absolute_precision: 0.,
+            min_replications: 10,
+            max_replications: 1000,
+            batch_size: rayon::current_num_threads(),
+        }
+    }
+
+    /// Sets the confidence level of the intervals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `confidence` is not between 0 and 1.
+    pub fn confidence(mut self, confidence: f64) -> Self {
+        assert!(
+            confidence > 0. && confidence < 1.,
+            "confidence must be between 0 and 1"
+        );
+        self.confidence = confidence;
+        self
+    }
+
+    /// Sets the targeted half width of the intervals relative to the mean.
+    pub fn relative_precision(mut self, relative_precision: f64) -> Self {
+        self.relative_precision = relative_precision;
+        self
+    }
+
+    /// Sets the targeted half width of the intervals in the unit of the metrics.
+    ///
+    /// A metric has reached the target precision, if either the relative or the absolute
+    /// precision is met. Defaults to zero, so only the relative precision is used.
+    pub fn absolute_precision(mut self, absolute_precision: f64) -> Self {
+        self.absolute_precision = absolute_precision;
+        self
+    }
+
+    /// Sets the minimum number of replications. At least two replications are always run.
+    pub fn min_replications(mut self, replications: usize) -> Self {
+        self.min_replications = replications;
+        self
+    }
+
+    /// Sets the maximum number of replications.
+    pub fn max_replications(mut self, replications: usize) -> Self {
+        self.max_replications = replications;
+        self
+    }
+
+    /// Sets the number of replications run in parallel before checking the precision.
+    ///
+    /// Defaults to the number of threads used by rayon.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn estimates(&self, stats: &[RunningStats]) -> Vec<Estimate> {
+        self.metrics
+            .iter()
+            .zip(stats)
+            .map(|(name, stats)| Estimate {
+                name: name.clone(),
+                stats: *stats,
+                half_width: stats.half_width(self.confidence),
+            })
+            .collect()
+    }
+
+    /// Runs replications until the target precision or the maximum number of replications is
+    /// reached.
+    ///
+    /// `replicate` is called with the index of the replication, which should be used to seed the
+    /// simulation, and returns the value of every metric in the same order as the names passed
+    /// to [`ReplicationController::new()`]. The results are added in the order of the
+    /// replications, so the report only depends on the values, not on the thread scheduling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `replicate` returns a different number of values than metrics were specified.
+    pub fn run<F>(&self, replicate: F) -> Report
+    where
+        F: Fn(usize) -> Vec<f64> + Sync,
+    {
+        let min_replications = self.min_replications.max(2);
+        let mut stats = vec![RunningStats::new(); self.metrics.len()];
+        let mut replications = 0;
+        loop {
+            let batch = self
+                .batch_size
+                .min(self.max_replications.saturating_sub(replications));
+            let results = (replications..replications + batch)
+                .into_par_iter()
+                .map(&replicate)
+                .collect::<Vec<_>>();
+            for values in results {
+                assert_eq!(
+                    values.len(),
+                    stats.len(),
+                    "number of values does not match the number of metrics"
+                );
+                for (stats, value) in stats.iter_mut().zip(values) {
+                    stats.push(value);
+                }
+            }
+            replications += batch;
+
+            let estimates = self.estimates(&stats);
+            let converged = replications >= min_replications
+                && estimates.iter().all(|estimate| {
+                    estimate.half_width <= self.absolute_precision
+                        || estimate.relative_half_width() <= self.relative_precision
+                });
+            if converged || replications >= self.max_replications {
+                return Report {
+                    estimates,
+                    replications,
+                    confidence: self.confidence,
+                    converged,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normal_quantile, student_t_quantile, ReplicationController, RunningStats};
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::new();
+        assert!(stats.half_width(0.95).is_infinite());
+        for value in &[2., 4., 4., 4., 5., 5., 7., 9.] {
+            stats.push(*value);
+        }
+        assert_eq!(stats.count(), 8);
+        assert!((stats.mean() - 5.).abs() < 1e-12);
+        assert!((stats.variance() - 32. / 7.).abs() < 1e-12);
+        // t(0.975, 7) = 2.3646
+        let expected = 2.3646 * (32_f64 / 7.).sqrt() / 8_f64.sqrt();
+        assert!((stats.half_width(0.95) - expected).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_quantiles() {
+        assert!(normal_quantile(0.5).abs() < 1e-9);
+        assert!((normal_quantile(0.975) - 1.959_964).abs() < 1e-6);
+        assert!((normal_quantile(0.01) + 2.326_348).abs() < 1e-6);
+        assert!((student_t_quantile(0.975, 10.) - 2.228_139).abs() < 1e-3);
+        assert!((student_t_quantile(0.995, 30.) - 2.749_996).abs() < 1e-3);
+        assert!((student_t_quantile(0.975, 1.) - 12.706_205).abs() < 1e-6);
+        assert!((student_t_quantile(0.975, 2.) - 4.302_653).abs() < 1e-6);
+        assert!((student_t_quantile(0.975, 3.) - 3.182_446).abs() < 1e-6);
+        assert!((student_t_quantile(0.995, 3.) - 5.840_909).abs() < 1e-6);
+        assert!((student_t_quantile(0.025, 3.) + 3.182_446).abs() < 1e-6);
+        assert!((student_t_quantile(0.975, 4.) - 2.776_445).abs() < 1e-2);
+    }
+
+    fn noise(replication: usize) -> f64 {
+        // A deterministic, roughly uniform value in [-0.5, 0.5)
+        let hash = (replication as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        (hash >> 11) as f64 / (1_u64 << 53) as f64 - 0.5
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_converges() {
+        let controller = ReplicationController::new(vec!["small noise", "large noise"])
+            .relative_precision(0.05)
+            .batch_size(4);
+        let report = controller.run(|replication| {
+            vec![
+                100. + noise(replication),
+                10. + 10. * noise(replication + 1000),
+            ]
+        });
+        assert!(report.converged);
+        assert_eq!(report.replications % 4, 0);
+        assert!(report.replications >= 12);
+        for estimate in &report.estimates {
+            assert!(estimate.relative_half_width() <= 0.05);
+        }
+        let (lower, upper) = report.estimates[1].interval();
+        assert!(lower < 10. && upper > 10.);
+        assert_eq!(
+            report,
+            controller.run(|replication| {
+                vec![
+                    100. + noise(replication),
+                    10. + 10. * noise(replication + 1000),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_max_replications() {
+        let report = ReplicationController::new(vec!["noise"])
+            .max_replications(30)
+            .batch_size(8)
+            .run(|replication| vec![noise(replication)]);
+        assert!(!report.converged);
+        assert_eq!(report.replications, 30);
+        assert_eq!(report.estimates[0].stats.count(), 30);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_zero_mean() {
+        let report = ReplicationController::new(vec!["zero"])
+            .batch_size(4)
+            .run(|_| vec![0.]);
+        assert!(report.converged);
+        assert_eq!(report.replications, 12);
+        assert_eq!(report.estimates[0].relative_half_width(), 0.);
+
+        let report = ReplicationController::new(vec!["noise"])
+            .max_replications(100)
+            .batch_size(4)
+            .absolute_precision(0.1)
+            .run(|replication| vec![noise(replication)]);
+        assert!(report.converged);
+        assert!(report.estimates[0].half_width <= 0.1);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    fmt,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{Agent, Simulation, Snapshot};
+
+/// A command sent to a [`SimulationRunner`].
+pub enum Command<A: Agent> {
+    /// Stops advancing the simulation.
+    Pause,
+    /// Continuously advances the simulation.
+    Resume,
+    /// Advances the simulation by the specified number of ticks and pauses afterwards.
+    Step(u64),
+    /// Limits the number of ticks simulated per second. `None` removes the limit.
+    ///
+    /// A rate so low, that the time between two ticks cannot be represented, pauses the simulation
+    /// and keeps the previous limit.
+    SetTicksPerSecond(Option<f64>),
+    /// Runs a function on the simulation, e.g. to rewind it.
+    #[allow(clippy::type_complexity)]
+    Apply(Box<dyn FnOnce(&mut Simulation<A>) + Send>),
+    /// Sends a snapshot after every tick to the sender.
+    Subscribe(Sender<Arc<Snapshot<A>>>),
+    /// Stops the runner.
+    Stop,
+}
+
+impl<A: Agent> fmt::Debug for Command<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pause => f.write_str("Pause"),
+            Self::Resume => f.write_str("Resume"),
+            Self::Step(ticks) => f.debug_tuple("Step").field(ticks).finish(),
+            Self::SetTicksPerSecond(tps) => f.debug_tuple("SetTicksPerSecond").field(tps).finish(),
+            Self::Apply(_) => f.write_str("Apply(..)"),
+            Self::Subscribe(_) => f.write_str("Subscribe(..)"),
+            Self::Stop => f.write_str("Stop"),
+        }
+    }
+}
+
+/// Runs a [`Simulation`] on a background thread.
+///
+/// The runner is controlled by [`Command`]s sent over a channel. After every tick, a
+/// [`Snapshot`] of the simulation is published, which can be read by
+/// [`SimulationRunner::snapshot()`] without blocking the simulation, e.g. by a renderer.
+///
+/// The runner starts paused.
+pub struct SimulationRunner<A: Agent> {
+    commands: Sender<Command<A>>,
+    snapshot: Arc<Mutex<Arc<Snapshot<A>>>>,
+    handle: Option<JoinHandle<Simulation<A>>>,
+}
+
+impl<A> SimulationRunner<A>
+where
+    A: Agent + Clone + 'static,
+    A::State: Clone + 'static,
+    A::World: Clone + Send + 'static,
+{
+    /// Moves the simulation to a new thread.
+    pub fn spawn(simulation: Simulation<A>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let snapshot = Arc::new(Mutex::new(Arc::new(simulation.snapshot())));
+        let shared = Arc::clone(&snapshot);
+        let handle = thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn(move || Worker::new(simulation, shared).run(&receiver))
+            .expect("failed to spawn simulation thread");
+        Self {
+            commands,
+            snapshot,
+            handle: Some(handle),
+        }
+    }
+
+    /// Sends a command to the runner.
+    ///
+    /// Commands sent after the runner was stopped are ignored.
+    pub fn send(&self, command: Command<A>) {
+        // The worker only exits after `Stop`, so a failed send can be ignored
+        let _ = self.commands.send(command);
+    }
+
+    /// Stops advancing the simulation.
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Continuously advances the simulation.
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    /// Advances the simulation by `ticks` ticks and pauses afterwards.
+    pub fn step(&self, ticks: u64) {
+        self.send(Command::Step(ticks));
+    }
+
+    /// Limits the number of ticks simulated per second. `None` removes the limit.
+    ///
+    /// A rate so low, that the time between two ticks cannot be represented, pauses the simulation
+    /// and keeps the previous limit.
+    pub fn set_ticks_per_second(&self, ticks_per_second: Option<f64>) {
+        self.send(Command::SetTicksPerSecond(ticks_per_second));
+    }
+
+    /// Runs `f` on the simulation thread with unique access to the simulation.
+    pub fn apply(&self, f: impl FnOnce(&mut Simulation<A>) + Send + 'static) {
+        self.send(Command::Apply(Box::new(f)));
+    }
+
+    /// Returns a receiver, which receives a snapshot after every tick.
+    ///
+    /// Unlike [`SimulationRunner::snapshot()`], no tick is missed.
+    pub fn subscribe(&self) -> Receiver<Arc<Snapshot<A>>> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(Command::Subscribe(sender));
+        receiver
+    }
+
+    /// Returns the latest published snapshot.
+    pub fn snapshot(&self) -> Arc<Snapshot<A>> {
+        Arc::clone(&self.snapshot.lock().expect("simulation thread panicked"))
+    }
+
+    /// Stops the runner and returns the simulation.
+    ///
+    /// # Panics
+    ///
+    /// Propagates a panic of the simulation thread.
+    pub fn stop(mut self) -> Simulation<A> {
+        self.send(Command::Stop);
+        let handle = self.handle.take().expect("runner was already stopped");
+        match handle.join() {
+            Ok(simulation) => simulation,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<A: Agent> Drop for SimulationRunner<A> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = self.commands.send(Command::Stop);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The state of the simulation thread.
+struct Worker<A: Agent> {
+    simulation: Simulation<A>,
+    snapshot: Arc<Mutex<Arc<Snapshot<A>>>>,
+    subscribers: Vec<Sender<Arc<Snapshot<A>>>>,
+    running: bool,
+    pending_steps: u64,
+    interval: Option<Duration>,
+    next_tick: Instant,
+}
+
+impl<A> Worker<A>
+where
+    A: Agent + Clone,
+    A::State: Clone,
+    A::World: Clone,
+{
+    fn new(simulation: Simulation<A>, snapshot: Arc<Mutex<Arc<Snapshot<A>>>>) -> Self {
+        Self {
+            simulation,
+            snapshot,
+            subscribers: Vec::new(),
+            running: false,
+            pending_steps: 0,
+            interval: None,
+            next_tick: Instant::now(),
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.running || self.pending_steps > 0
+    }
+
+    /// Handles a command. Returns `false`, if the worker should stop.
+    fn handle(&mut self, command: Command<A>) -> bool {
+        match command {
+            Command::Pause => {
+                self.running = false;
+                self.pending_steps = 0;
+            }
+            Command::Resume => self.running = true,
+            Command::Step(ticks) => {
+                self.running = false;
+                self.pending_steps = self.pending_steps.saturating_add(ticks);
+            }
+            Command::SetTicksPerSecond(tps) => match tps.filter(|tps| *tps > 0.) {
+                Some(tps) => {
+                    let interval = Duration::try_from_secs_f64(1. / tps)
+                        .ok()
+                        .filter(|interval| Instant::now().checked_add(*interval).is_some());
+                    if interval.is_none() {
+                        // The next tick would never be due
+                        self.running = false;
+                        self.pending_steps = 0;
+                    }
+                    self.interval = interval.or(self.interval);
+                }
+                None => self.interval = None,
+            },
+            Command::Apply(f) => {
+                f(&mut self.simulation);
+                self.publish();
+            }
+            Command::Subscribe(subscriber) => self.subscribers.push(subscriber),
+            Command::Stop => return false,
+        }
+        true
+    }
+
+    fn publish(&mut self) {
+        let snapshot = Arc::new(self.simulation.snapshot());
+        *self.snapshot.lock().expect("renderer panicked") = Arc::clone(&snapshot);
+        self.subscribers
+            .retain(|subscriber| subscriber.send(Arc::clone(&snapshot)).is_ok());
+    }
+
+    fn run(mut self, commands: &Receiver<Command<A>>) -> Simulation<A> {
+        loop {
+            // Wait for the next command, while there is nothing to do, or until the next tick
+            let command = if !self.active() {
+                commands.recv().ok()
+            } else if let Some(timeout) = self
+                .interval
+                .and_then(|_| self.next_tick.checked_duration_since(Instant::now()))
+            {
+                match commands.recv_timeout(timeout) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            } else {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => {
+                        self.tick();
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+
+            if !command.is_some_and(|command| self.handle(command)) {
+                return self.simulation;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        self.simulation.update();
+        self.pending_steps = self.pending_steps.saturating_sub(1);
+        if let Some(interval) = self.interval {
+            // Don't try to catch up with ticks missed while paused
+            let earliest = Instant::now()
+                .checked_sub(interval)
+                .unwrap_or(self.next_tick);
+            self.next_tick = self.next_tick.max(earliest) + interval;
+        }
+        self.publish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{Agent, Simulation, SimulationRunner, World};
+
+    #[derive(Clone)]
+    struct Counter;
+    impl Agent for Counter {
+        type State = u64;
+        type World = Ticks;
+
+        fn on_update(
+            &self,
+            _id: usize,
+            state: &mut Self::State,
+            _world: &Self::World,
+            _population: &[(Self, Self::State)],
+        ) {
+            *state += 1;
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Ticks(u64);
+    impl World<Counter> for Ticks {
+        fn update(&mut self, _agents: &mut [(Counter, u64)]) {
+            self.0 += 1;
+        }
+    }
+
+    fn runner() -> SimulationRunner<Counter> {
+        let mut simulation = Simulation::new(Ticks::default());
+        simulation.add_agent(Counter, 0);
+        simulation.add_agent(Counter, 10);
+        SimulationRunner::spawn(simulation)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_step() {
+        let runner = runner();
+        assert_eq!(runner.snapshot().tick(), 0);
+
+        let snapshots = runner.subscribe();
+        runner.step(5);
+        let ticks = snapshots
+            .iter()
+            .take(5)
+            .map(|s| s.tick())
+            .collect::<Vec<_>>();
+        assert_eq!(ticks, vec![1, 2, 3, 4, 5]);
+        assert_eq!(runner.snapshot().world().0, 5);
+
+        runner.apply(|simulation| simulation.agents_mut()[0].1 = 100);
+        assert_eq!(snapshots.recv().unwrap().agents()[0].1, 100);
+
+        let simulation = runner.stop();
+        assert_eq!(simulation.tick(), 5);
+        assert_eq!(simulation.agents()[1].1, 15);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_resume() {
+        let runner = runner();
+        let snapshots = runner.subscribe();
+
+        runner.set_ticks_per_second(Some(200.));
+        runner.resume();
+        let start = Instant::now();
+        let tenth = snapshots.iter().nth(9).unwrap();
+        assert_eq!(tenth.tick(), 10);
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        runner.pause();
+        let paused = runner.stop();
+        let last = snapshots.try_iter().last().unwrap_or(tenth);
+        assert_eq!(last.tick(), paused.tick());
+        assert_eq!(last.world().0, paused.world().0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_tiny_ticks_per_second() {
+        let runner = runner();
+        let snapshots = runner.subscribe();
+        runner.set_ticks_per_second(Some(1e-300));
+        runner.step(3);
+        assert_eq!(snapshots.iter().nth(2).unwrap().tick(), 3);
+        assert_eq!(runner.stop().tick(), 3);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc};
+
+use crate::Agent;
+
+/// Decides, when an agent is updated next.
+///
+/// Returned by [`Agent::wakeup()`] after every update of the agent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Wakeup {
+    /// Update the agent in the next tick.
+    #[default]
+    Next,
+    /// Update the agent again in the specified number of ticks. `In(1)` is the same as
+    /// [`Wakeup::Next`], `In(0)` is treated like `In(1)`.
+    In(u64),
+    /// Don't update the agent until it is woken up by [`Simulation::wake()`].
+    ///
+    /// [`Simulation::wake()`]: crate::Simulation::wake()
+    Suspend,
+}
+
+/// The period, in which an agent or the world is updated.
+///
+/// An update is due in every tick, for which `tick % every == offset % every` holds. The default
+/// period updates in every tick.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Period {
+    every: u64,
+    offset: u64,
+}
+
+impl Period {
+    /// A period updating in every tick.
+    pub const EVERY_TICK: Self = Self {
+        every: 1,
+        offset: 0,
+    };
+
+    /// Creates a period, which is due every `every` ticks shifted by `offset` ticks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every` is zero.
+    pub fn new(every: u64, offset: u64) -> Self {
+        assert_ne!(every, 0, "period must not be zero");
+        Self {
+            every,
+            offset: offset % every,
+        }
+    }
+
+    /// Returns the number of ticks between two updates.
+    #[inline]
+    pub fn every(self) -> u64 {
+        self.every
+    }
+
+    /// Returns the phase offset of the period.
+    #[inline]
+    pub fn offset(self) -> u64 {
+        self.offset
+    }
+
+    /// Returns if an update is due in `tick`.
+    #[inline]
+    pub fn is_due(self, tick: u64) -> bool {
+        tick % self.every == self.offset
+    }
+
+    /// Returns the first tick starting from `tick`, in which an update is due.
+    pub fn next_due(self, tick: u64) -> u64 {
+        let remainder = (self.offset + self.every - tick % self.every) % self.every;
+        tick.saturating_add(remainder)
+    }
+}
+
+impl Default for Period {
+    fn default() -> Self {
+        Self::EVERY_TICK
+    }
+}
+
+/// Describes a tick, which is about to be simulated.
+///
+/// Passed to [`World::begin_tick()`] before any agent is updated and available for the upcoming
+/// tick from [`Simulation::tick_context()`].
+///
+/// [`World::begin_tick()`]: crate::World::begin_tick()
+/// [`Simulation::tick_context()`]: crate::Simulation::tick_context()
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TickContext {
+    /// The simulated tick.
+    pub tick: u64,
+    /// The number of agents, which are updated in this tick.
+    pub due_agents: usize,
+    /// If [`World::update()`] is invoked at the end of this tick.
+    ///
+    /// [`World::update()`]: crate::World::update()
+    pub world_due: bool,
+    /// The period, in which the world is updated.
+    pub world_period: Period,
+    /// The seed of the simulation set by [`Simulation::set_seed()`].
+    ///
+    /// [`Simulation::set_seed()`]: crate::Simulation::set_seed()
+    pub seed: u64,
+}
+
+impl TickContext {
+    /// Returns a seed for a random number generator of the agent with the specified `id`.
+    ///
+    /// The seed only depends on the simulation seed, the tick and the `id`. Agents, which draw
+    /// all random numbers from a generator seeded this way, behave exactly the same, when a tick
+    /// is simulated again, e.g. after [`Simulation::rewind()`].
+    ///
+    /// [`Simulation::rewind()`]: crate::Simulation::rewind()
+    pub fn seed_for(&self, id: usize) -> u64 {
+        mix(mix(mix(self.seed) ^ self.tick) ^ id as u64)
+    }
+}
+
+/// Mixes the bits of `z` as in the finalizer of SplitMix64.
+pub(crate) fn mix(z: u64) -> u64 {
+    let z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A small random generator for sampling designs, which does not depend on `rand`.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let value = mix(self.0);
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        value
+    }
+
+    /// Returns a value in `[0, 1)`.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Returns a value from the standard normal distribution using the Box-Muller transform.
+    pub(crate) fn normal(&mut self) -> f64 {
+        let radius = (-2. * (1. - self.uniform()).ln()).sqrt();
+        radius * (2. * std::f64::consts::PI * self.uniform()).cos()
+    }
+
+    /// Returns a value in `0..n`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Shuffles `values` with the Fisher-Yates algorithm.
+    pub(crate) fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = self.below(i + 1);
+            values.swap(i, j);
+        }
+    }
+}
+
+/// An event, which is scheduled to be executed at a specific tick.
+///
+/// Events are able to mutate the global state and all agents, similar to [`World::update()`].
+///
+/// [`World::update()`]: crate::World::update()
+pub type Event<A> =
+    Arc<dyn Fn(&mut <A as Agent>::World, &mut [(A, <A as Agent>::State)]) + Send + Sync>;
+
+struct ScheduledEvent<A: Agent> {
+    tick: u64,
+    sequence: u64,
+    event: Event<A>,
+}
+
+impl<A: Agent> Clone for ScheduledEvent<A> {
+    fn clone(&self) -> Self {
+        Self {
+            tick: self.tick,
+            sequence: self.sequence,
+            event: Arc::clone(&self.event),
+        }
+    }
+}
+
+impl<A: Agent> PartialEq for ScheduledEvent<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<A: Agent> Eq for ScheduledEvent<A> {}
+
+impl<A: Agent> PartialOrd for ScheduledEvent<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Agent> Ord for ScheduledEvent<A> {
+    // `BinaryHeap` is a max-heap, so the order is reversed to pop the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.tick, other.sequence).cmp(&(self.tick, self.sequence))
+    }
+}
+
+/// The schedule of a single agent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct AgentSchedule {
+    /// The earliest tick, at which the agent is updated next. `u64::MAX` marks a suspended agent.
+    pub(crate) next_update: u64,
+    pub(crate) period: Period,
+    /// The tick, in which the agent was updated for the first time.
+    pub(crate) born: u64,
+}
+
+impl AgentSchedule {
+    pub(crate) fn is_due(self, tick: u64) -> bool {
+        self.next_update <= tick && self.period.is_due(tick)
+    }
+
+    /// Returns the first tick starting from `tick`, in which the agent is due.
+    fn next_due(self, tick: u64) -> Option<u64> {
+        match self.next_update {
+            u64::MAX => None,
+            next => Some(self.period.next_due(next.max(tick))),
+        }
+    }
+}
+
+/// Keeps track of the ticks, at which agents are updated next, and of scheduled events.
+pub(crate) struct Scheduler<A: Agent> {
+    agents: Vec<AgentSchedule>,
+    world_period: Period,
+    events: BinaryHeap<ScheduledEvent<A>>,
+    sequence: u64,
+}
+
+impl<A: Agent> Clone for Scheduler<A> {
+    fn clone(&self) -> Self {
+        Self {
+            agents: self.agents.clone(),
+            world_period: self.world_period,
+            events: self.events.clone(),
+            sequence: self.sequence,
+        }
+    }
+}
+
+impl<A: Agent> Scheduler<A> {
+    pub(crate) fn with_capacity(num_agent: usize) -> Self {
+        Self {
+            agents: Vec::with_capacity(num_agent),
+            world_period: Period::EVERY_TICK,
+            events: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Registers a new agent, which is updated in `tick`.
+    pub(crate) fn add_agent(&mut self, tick: u64) {
+        self.agents.push(AgentSchedule {
+            next_update: tick,
+            period: Period::EVERY_TICK,
+            born: tick,
+        });
+    }
+
+    pub(crate) fn remove_agent(&mut self, id: usize) {
+        self.agents.remove(id);
+    }
+
+    /// Removes the schedule of every agent, for which `dead` is set.
+    pub(crate) fn remove_agents(&mut self, dead: &[bool]) {
+        let mut dead = dead.iter();
+        self.agents
+            .retain(|_| !dead.next().copied().unwrap_or(false));
+    }
+
+    pub(crate) fn agent(&self, id: usize) -> AgentSchedule {
+        self.agents[id]
+    }
+
+    pub(crate) fn agents_mut(&mut self) -> &mut [AgentSchedule] {
+        &mut self.agents
+    }
+
+    /// Returns the first tick starting from `tick`, in which the agent is updated.
+    pub(crate) fn next_update(&self, id: usize, tick: u64) -> Option<u64> {
+        self.agents[id].next_due(tick)
+    }
+
+    pub(crate) fn set_next_update(&mut self, id: usize, tick: u64) {
+        self.agents[id].next_update = tick;
+    }
+
+    pub(crate) fn set_period(&mut self, id: usize, period: Period) {
+        self.agents[id].period = period;
+    }
+
+    pub(crate) fn world_period(&self) -> Period {
+        self.world_period
+    }
+
+    pub(crate) fn set_world_period(&mut self, period: Period) {
+        self.world_period = period;
+    }
+
+    pub(crate) fn context(&self, tick: u64, seed: u64) -> TickContext {
+        TickContext {
+            tick,
+            due_agents: self
+                .agents
+                .iter()
+                .filter(|agent| agent.is_due(tick))
+                .count(),
+            world_due: self.world_period.is_due(tick),
+            world_period: self.world_period,
+            seed,
+        }
+    }
+
+    pub(crate) fn schedule(&mut self, tick: u64, event: Event<A>) {
+        self.events.push(ScheduledEvent {
+            tick,
+            sequence: self.sequence,
+            event,
+        });
+        self.sequence += 1;
+    }
+
+    /// Removes and returns the earliest event scheduled at or before `tick`.
+    pub(crate) fn pop_event(&mut self, tick: u64) -> Option<Event<A>> {
+        if self.events.peek()?.tick <= tick {
+            self.events.pop().map(|scheduled| scheduled.event)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the earliest tick starting from `tick`, at which an agent is updated or an event
+    /// is executed.
+    pub(crate) fn next_event(&self, tick: u64) -> Option<u64> {
+        let agent = self
+            .agents
+            .iter()
+            .filter_map(|agent| agent.next_due(tick))
+            .min();
+        let event = self.events.peek().map(|scheduled| scheduled.tick);
+        match (agent, event) {
+            (Some(agent), Some(event)) => Some(agent.min(event)),
+            (next, None) | (None, next) => next,
+        }
+        .map(|next| next.max(tick))
+    }
+}
+
+impl Wakeup {
+    /// Returns the tick, at which an agent updated in `tick` is updated next.
+    pub(crate) fn next_update(self, tick: u64) -> u64 {
+        match self {
+            Self::Next => tick + 1,
+            Self::In(ticks) => tick.saturating_add(ticks.max(1)),
+            Self::Suspend => u64::MAX,
+        }
+    }
+}
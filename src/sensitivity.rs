@@ -0,0 +1,595 @@
+use std::{cmp::Ordering, ops::Range};
+
+use rayon::prelude::*;
+
+use crate::schedule::SplitMix64;
+
+/// A sampling design for a [`SensitivityAnalysis`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Design {
+    /// Latin hypercube sampling with the given number of samples.
+    ///
+    /// Every factor is split into as many strata as samples, and every stratum is sampled
+    /// exactly once. The factors are ranked by Spearman's rank correlation with the output.
+    LatinHypercube { samples: usize },
+    /// Saltelli's scheme on a Sobol' sequence with the given number of base samples.
+    ///
+    /// The model is evaluated `samples * (factors + 2)` times. The factors are ranked by their
+    /// total Sobol' index, which includes interactions with other factors.
+    Sobol { samples: usize },
+    /// Morris' one-at-a-time screening with the given number of trajectories on a grid with
+    /// `levels` values per factor.
+    ///
+    /// The model is evaluated `trajectories * (factors + 1)` times. The factors are ranked by the
+    /// mean absolute elementary effect `μ*`.
+    Morris { trajectories: usize, levels: usize },
+}
+
+/// An input of the model, which is varied in a [`SensitivityAnalysis`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Factor {
+    /// The name of the factor.
+    pub name: String,
+    /// The values of the factor.
+    pub range: Range<f64>,
+}
+
+/// The sensitivity indices of a factor.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Indices {
+    /// Spearman's rank correlation between the factor and the output.
+    RankCorrelation(f64),
+    /// The first order Sobol' index, which is the fraction of the output variance caused by the
+    /// factor alone, and the total index, which includes all interactions with other factors.
+    Sobol { first_order: f64, total: f64 },
+    /// The mean `μ`, the mean of the absolute values `μ*` and the standard deviation `σ` of the
+    /// elementary effects. The effects are measured on the factor range scaled to `[0, 1]`.
+    Morris { mu: f64, mu_star: f64, sigma: f64 },
+}
+
+/// The sensitivity of an output to a factor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactorSensitivity {
+    /// The name of the factor.
+    pub factor: String,
+    /// The computed indices.
+    pub indices: Indices,
+}
+
+impl FactorSensitivity {
+    /// Returns the index, which is used to rank the factors.
+    pub fn importance(&self) -> f64 {
+        match self.indices {
+            Indices::RankCorrelation(correlation) => correlation.abs(),
+            Indices::Sobol { total, .. } => total,
+            Indices::Morris { mu_star, .. } => mu_star,
+        }
+    }
+}
+
+/// Finds the factors, which have the most influence on the outputs of a model.
+///
+/// The analysis generates sample points for the factors with the chosen [`Design`]. After the
+/// model was evaluated at every point, [`SensitivityAnalysis::analyze()`] computes the indices for
+/// an output and ranks the factors by influence.
+///
+/// ```
+/// use tag_game::{Design, SensitivityAnalysis};
+///
+/// let analysis = SensitivityAnalysis::new(Design::Sobol { samples: 1024 })
+///     .factor("weak", 0.0..1.0)
+///     .factor("strong", 0.0..1.0);
+///
+/// let outputs = analysis.run(|x| vec![x[0] + 10. * x[1]]);
+/// assert_eq!(outputs[0][0].factor, "strong");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityAnalysis {
+    design: Design,
+    factors: Vec<Factor>,
+    seed: u64,
+}
+
+/// An elementary effect of a Morris trajectory: the point before the step, the factor, and the
+/// signed step size.
+type Move = (usize, usize, f64);
+
+impl SensitivityAnalysis {
+    /// Creates an analysis without factors.
+    pub fn new(design: Design) -> Self {
+        Self {
+            design,
+            factors: Vec::new(),
+            seed: 0,
+        }
+    }
+
+    /// Adds a factor with the given range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty.
+    pub fn factor(mut self, name: impl Into<String>, range: Range<f64>) -> Self {
+        assert!(
+            range.start < range.end,
+            "the range of a factor must not be empty"
+        );
+        self.factors.push(Factor {
+            name: name.into(),
+            range,
+        });
+        self
+    }
+
+    /// Sets the seed for the random parts of the design.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the design.
+    pub fn design(&self) -> Design {
+        self.design
+    }
+
+    /// Returns the factors in the order they were added.
+    pub fn factors(&self) -> &[Factor] {
+        &self.factors
+    }
+
+    /// Returns the unit points and, for Morris' design, the steps of the trajectories.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn plan(&self) -> (Vec<Vec<f64>>, Vec<Move>) {
+        let dimensions = self.factors.len();
+        let mut rng = SplitMix64(self.seed);
+        match self.design {
+            Design::LatinHypercube { samples } => {
+                let mut points = vec![vec![0.; dimensions]; samples];
+                for factor in 0..dimensions {
+                    let mut strata = (0..samples).collect::<Vec<_>>();
+                    rng.shuffle(&mut strata);
+                    for (point, stratum) in points.iter_mut().zip(strata) {
+                        point[factor] = (stratum as f64 + rng.uniform()) / samples as f64;
+                    }
+                }
+                (points, Vec::new())
+            }
+            Design::Sobol { samples } => {
+                let mut sequence = Sobol::new(2 * dimensions);
+                let mut points = Vec::with_capacity(samples * (dimensions + 2));
+                for _ in 0..samples {
+                    let point = sequence.next_point();
+                    let (a, b) = point.split_at(dimensions);
+                    points.push(a.to_vec());
+                    points.push(b.to_vec());
+                    for factor in 0..dimensions {
+                        let mut ab = a.to_vec();
+                        ab[factor] = b[factor];
+                        points.push(ab);
+                    }
+                }
+                (points, Vec::new())
+            }
+            Design::Morris {
+                trajectories,
+                levels,
+            } => {
+                assert!(
+                    levels >= 2 && levels % 2 == 0,
+                    "the number of levels must be even"
+                );
+                let grid = (levels - 1) as f64;
+                let delta = levels as f64 / (2. * grid);
+                let mut points = Vec::with_capacity(trajectories * (dimensions + 1));
+                let mut moves = Vec::with_capacity(trajectories * dimensions);
+                for _ in 0..trajectories {
+                    let mut point = (0..dimensions)
+                        .map(|_| (rng.uniform() * levels as f64).floor() / grid)
+                        .collect::<Vec<_>>();
+                    let mut order = (0..dimensions).collect::<Vec<_>>();
+                    rng.shuffle(&mut order);
+                    points.push(point.clone());
+                    for factor in order {
+                        let step = if point[factor] + delta <= 1. + 1e-9 {
+                            delta
+                        } else {
+                            -delta
+                        };
+                        point[factor] += step;
+                        moves.push((points.len() - 1, factor, step));
+                        points.push(point.clone());
+                    }
+                }
+                (points, moves)
+            }
+        }
+    }
+
+    /// Returns the points, at which the model has to be evaluated.
+    ///
+    /// Every point contains one value per factor in the order the factors were added.
+    pub fn points(&self) -> Vec<Vec<f64>> {
+        self.plan()
+            .0
+            .into_iter()
+            .map(|point| {
+                point
+                    .iter()
+                    .zip(&self.factors)
+                    .map(|(x, factor)| {
+                        factor.range.start + x * (factor.range.end - factor.range.start)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Computes the sensitivity indices for one output of the model.
+    ///
+    /// `outputs` contains the value of the output for every point returned by
+    /// [`SensitivityAnalysis::points()`]. The factors are returned ranked by influence, the most
+    /// influential first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs does not match the number of points.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn analyze(&self, outputs: &[f64]) -> Vec<FactorSensitivity> {
+        let (points, moves) = self.plan();
+        assert_eq!(
+            outputs.len(),
+            points.len(),
+            "number of outputs does not match the number of points"
+        );
+        let dimensions = self.factors.len();
+
+        let indices = (0..dimensions).map(|factor| match self.design {
+            Design::LatinHypercube { .. } => {
+                let values = points.iter().map(|point| point[factor]).collect::<Vec<_>>();
+                Indices::RankCorrelation(correlation(&ranks(&values), &ranks(outputs)))
+            }
+            Design::Sobol { samples } => {
+                let stride = dimensions + 2;
+                let base = outputs
+                    .chunks(stride)
+                    .flat_map(|chunk| &chunk[..2])
+                    .copied()
+                    .collect::<Vec<_>>();
+                let variance = variance(&base);
+                // Centering the outputs does not change the estimators, but reduces their error
+                let mean = base.iter().sum::<f64>() / base.len().max(1) as f64;
+                let (mut first_order, mut total) = (0., 0.);
+                for chunk in outputs.chunks(stride) {
+                    let (a, b, ab) = (chunk[0] - mean, chunk[1] - mean, chunk[2 + factor] - mean);
+                    first_order += b * (ab - a);
+                    total += (a - ab) * (a - ab) / 2.;
+                }
+                let normalize = |sum: f64| {
+                    if variance > 0. {
+                        sum / samples as f64 / variance
+                    } else {
+                        0.
+                    }
+                };
+                Indices::Sobol {
+                    first_order: normalize(first_order),
+                    total: normalize(total),
+                }
+            }
+            Design::Morris { .. } => {
+                let effects = moves
+                    .iter()
+                    .filter(|(_, moved, _)| *moved == factor)
+                    .map(|&(point, _, step)| (outputs[point + 1] - outputs[point]) / step)
+                    .collect::<Vec<_>>();
+                let count = effects.len().max(1) as f64;
+                Indices::Morris {
+                    mu: effects.iter().sum::<f64>() / count,
+                    mu_star: effects.iter().map(|effect| effect.abs()).sum::<f64>() / count,
+                    sigma: variance(&effects).sqrt(),
+                }
+            }
+        });
+
+        let mut ranking = self
+            .factors
+            .iter()
+            .zip(indices)
+            .map(|(factor, indices)| FactorSensitivity {
+                factor: factor.name.clone(),
+                indices,
+            })
+            .collect::<Vec<_>>();
+        ranking.sort_by(|a, b| {
+            b.importance()
+                .partial_cmp(&a.importance())
+                .unwrap_or(Ordering::Equal)
+        });
+        ranking
+    }
+
+    /// Evaluates `model` at every point in parallel and analyzes every output.
+    ///
+    /// `model` returns the values of all outputs for a point. For every output, the ranked
+    /// factors are returned in the same order.
+    pub fn run<F>(&self, model: F) -> Vec<Vec<FactorSensitivity>>
+    where
+        F: Fn(&[f64]) -> Vec<f64> + Sync,
+    {
+        let results = self
+            .points()
+            .par_iter()
+            .map(|point| model(point))
+            .collect::<Vec<_>>();
+        let count = results.first().map_or(0, Vec::len);
+        (0..count)
+            .map(|output| {
+                let outputs = results.iter().map(|r| r[output]).collect::<Vec<_>>();
+                self.analyze(&outputs)
+            })
+            .collect()
+    }
+}
+
+/// Returns the sample variance of `values`.
+#[allow(clippy::cast_precision_loss)]
+fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Returns Pearson's correlation coefficient of `x` and `y`, or zero if one of them is constant.
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let (mean_x, mean_y) = (mean(x), mean(y));
+    let (mut xy, mut xx, mut yy) = (0., 0., 0.);
+    for (x, y) in x.iter().zip(y) {
+        xy += (x - mean_x) * (y - mean_y);
+        xx += (x - mean_x) * (x - mean_x);
+        yy += (y - mean_y) * (y - mean_y);
+    }
+    if xx > 0. && yy > 0. {
+        xy / (xx * yy).sqrt()
+    } else {
+        0.
+    }
+}
+
+/// Returns the ranks of `values`. Tied values get their mean rank.
+#[allow(clippy::cast_precision_loss)]
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap_or(Ordering::Equal));
+    let mut ranks = vec![0.; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end - 1) as f64 / 2.;
+        for &index in &order[start..end] {
+            ranks[index] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// The degree `s`, the coefficients `a`, and the initial direction numbers `m` of the primitive
+/// polynomials for the dimensions 2 to 16, as published by Joe and Kuo (new-joe-kuo-6.21201).
+const DIRECTIONS: [(u32, u32, &[u32]); 15] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+];
+
+/// The Sobol' low-discrepancy sequence in up to 16 dimensions, generated in Gray code order.
+///
+/// The first point, which is zero in every dimension, is skipped.
+struct Sobol {
+    directions: Vec<[u32; 32]>,
+    point: Vec<u32>,
+    index: u32,
+}
+
+impl Sobol {
+    fn new(dimensions: usize) -> Self {
+        let max_dimensions = DIRECTIONS.len() + 1;
+        assert!(
+            dimensions <= max_dimensions,
+            "a Sobol' design supports at most {} factors",
+            max_dimensions / 2
+        );
+        let mut directions = Vec::with_capacity(dimensions);
+        if dimensions > 0 {
+            let mut first = [0; 32];
+            for (bit, direction) in first.iter_mut().enumerate() {
+                *direction = 1 << (31 - bit);
+            }
+            directions.push(first);
+        }
+        for &(degree, coefficients, initial) in DIRECTIONS.iter().take(dimensions.max(1) - 1) {
+            let degree = degree as usize;
+            let mut v = [0_u32; 32];
+            for bit in 0..32 {
+                v[bit] = if bit < degree {
+                    initial[bit] << (31 - bit)
+                } else {
+                    let mut direction = v[bit - degree] ^ (v[bit - degree] >> degree);
+                    for k in 1..degree {
+                        if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                            direction ^= v[bit - k];
+                        }
+                    }
+                    direction
+                };
+            }
+            directions.push(v);
+        }
+        Self {
+            directions,
+            point: vec![0; dimensions],
+            index: 0,
+        }
+    }
+
+    fn next_point(&mut self) -> Vec<f64> {
+        let bit = self.index.trailing_ones() as usize;
+        self.index += 1;
+        self.point
+            .iter_mut()
+            .zip(&self.directions)
+            .map(|(x, directions)| {
+                *x ^= directions[bit];
+                f64::from(*x) / 4_294_967_296.
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ranks, Design, Indices, SensitivityAnalysis, Sobol};
+
+    #[test]
+    fn test_sobol_sequence() {
+        // The first 2^m points, including the skipped zero, fill every interval of size 2^-m
+        let mut sequence = Sobol::new(16);
+        let points = (0..127).map(|_| sequence.next_point()).collect::<Vec<_>>();
+        assert_eq!(&points[0][..2], &[0.5, 0.5]);
+        assert_eq!(&points[1][..2], &[0.75, 0.25]);
+        for dimension in 0..16 {
+            let mut bins = vec![0; 128];
+            bins[0] += 1;
+            for point in &points {
+                bins[(point[dimension] * 128.) as usize] += 1;
+            }
+            assert!(bins.iter().all(|&count| count == 1), "{}", dimension);
+        }
+        // The first two dimensions also fill every 16x8 box
+        let mut boxes = vec![0; 128];
+        boxes[0] += 1;
+        for point in &points {
+            boxes[(point[0] * 16.) as usize * 8 + (point[1] * 8.) as usize] += 1;
+        }
+        assert!(boxes.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_ranks() {
+        assert_eq!(ranks(&[3., 1., 2., 1.]), vec![3., 0.5, 2., 0.5]);
+    }
+
+    fn model(x: &[f64]) -> Vec<f64> {
+        // `x[1]` is most important, `x[0]` only matters together with `x[1]`, `x[2]` is unused
+        vec![x[0] * x[1] + 4. * x[1]]
+    }
+
+    fn analysis(design: Design) -> SensitivityAnalysis {
+        SensitivityAnalysis::new(design)
+            .factor("a", 0.0..1.0)
+            .factor("b", -1.0..1.0)
+            .factor("unused", 0.0..10.0)
+            .seed(3)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_latin_hypercube() {
+        let analysis = analysis(Design::LatinHypercube { samples: 50 });
+        let points = analysis.points();
+        assert_eq!(points.len(), 50);
+        for (factor, range) in analysis.factors().iter().map(|f| &f.range).enumerate() {
+            let mut strata = points
+                .iter()
+                .map(|point| (point[factor] - range.start) / (range.end - range.start) * 50.)
+                .map(|x| x as usize)
+                .collect::<Vec<_>>();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..50).collect::<Vec<_>>());
+        }
+
+        // The correlation does not find the interaction, as `a` has no effect on average
+        let ranking = &analysis.run(model)[0];
+        assert_eq!(ranking[0].factor, "b");
+        assert!(matches!(ranking[0].indices, Indices::RankCorrelation(c) if c > 0.9));
+        assert!(ranking[1..].iter().all(|s| s.importance() < 0.3));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_sobol() {
+        let analysis = analysis(Design::Sobol { samples: 4096 });
+        assert_eq!(analysis.points().len(), 4096 * 5);
+        let ranking = &analysis.run(model)[0];
+        let factors = ranking
+            .iter()
+            .map(|s| s.factor.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(factors, ["b", "a", "unused"]);
+        match ranking[0].indices {
+            Indices::Sobol { first_order, total } => {
+                assert!(first_order > 0.9 && total < 1.1);
+            }
+            _ => unreachable!(),
+        }
+        match ranking[2].indices {
+            Indices::Sobol { first_order, total } => {
+                assert!(first_order.abs() < 1e-9 && total.abs() < 1e-9);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_morris() {
+        let analysis = analysis(Design::Morris {
+            trajectories: 20,
+            levels: 4,
+        });
+        let points = analysis.points();
+        assert_eq!(points.len(), 20 * 4);
+        // Every step of a trajectory changes exactly one factor
+        for trajectory in points.chunks(4) {
+            for step in trajectory.windows(2) {
+                let changed = step[0].iter().zip(&step[1]).filter(|(a, b)| a != b);
+                assert_eq!(changed.count(), 1);
+            }
+        }
+
+        let ranking = &analysis.run(model)[0];
+        let factors = ranking
+            .iter()
+            .map(|s| s.factor.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(factors, ["b", "a", "unused"]);
+        match ranking[1].indices {
+            // The effect of `a` depends on `b`
+            Indices::Morris { sigma, .. } => assert!(sigma > 0.),
+            _ => unreachable!(),
+        }
+        assert_eq!(ranking[2].importance(), 0.);
+    }
+}
@@ -1,6 +1,11 @@
+use std::{convert::TryFrom, sync::Arc};
+
 use rayon::prelude::*;
 
-use crate::{Agent, World};
+use crate::{
+    history::History, invariant::Invariant, schedule::Scheduler, Agent, InvariantViolation, Period,
+    Population, Snapshot, SpatialGrid, TickContext, Ticks, World,
+};
 
 /// Keeps track of all [`Agent`]s, its states and the global state.
 ///
@@ -11,6 +16,14 @@ pub struct Simulation<A: Agent> {
     agents: Vec<(A, A::State)>,
     state_buffer: Vec<(A, A::State)>,
     world: A::World,
+    interaction_radius: Option<f32>,
+    grid: SpatialGrid,
+    tick: u64,
+    scheduler: Scheduler<A>,
+    invariants: Vec<Invariant<A>>,
+    seed: u64,
+    history: History<A>,
+    population: Population,
 }
 
 impl<A: Agent> Simulation<A> {
@@ -22,6 +35,14 @@ impl<A: Agent> Simulation<A> {
             world,
             agents: Vec::new(),
             state_buffer: Vec::new(),
+            interaction_radius: None,
+            grid: SpatialGrid::new(1.),
+            tick: 0,
+            scheduler: Scheduler::with_capacity(0),
+            invariants: Vec::new(),
+            seed: 0,
+            history: History::new(),
+            population: Population::default(),
         }
     }
 
@@ -32,9 +53,36 @@ impl<A: Agent> Simulation<A> {
             world,
             agents: Vec::with_capacity(num_agent),
             state_buffer: Vec::with_capacity(num_agent),
+            interaction_radius: None,
+            grid: SpatialGrid::new(1.),
+            tick: 0,
+            scheduler: Scheduler::with_capacity(num_agent),
+            invariants: Vec::new(),
+            seed: 0,
+            history: History::new(),
+            population: Population::default(),
         }
     }
 
+    /// Returns the tick, which is simulated by the next call to [`Simulation::update()`].
+    ///
+    /// This equals the number of simulated ticks, unless ticks were skipped with
+    /// [`Simulation::skip_to_next_event()`].
+    #[inline]
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Sets the tick, which is simulated by the next call to [`Simulation::update()`].
+    ///
+    /// This is mainly useful to restore a simulation, e.g. from a checkpoint. Sleeping agents and
+    /// scheduled [`Event`]s are not adjusted.
+    ///
+    /// [`Event`]: crate::Event
+    pub fn set_tick(&mut self, tick: u64) {
+        self.tick = tick;
+    }
+
     /// Returns a slice over all agents and its state added to the simulation.
     #[inline]
     pub fn agents(&self) -> &[(A, A::State)] {
@@ -49,16 +97,242 @@ impl<A: Agent> Simulation<A> {
 
     /// Add a new agent with an initial to the simulation.
     ///
-    /// This will call [`Agent::on_creation()`] when the agent was created.
+    /// This will call [`Agent::on_creation()`] when the agent was created. The agent is updated
+    /// for the first time in the next tick.
     ///
     /// Returns a unique identifier for the created agent.
-    pub fn add_agent(&mut self, agent: A, mut state: A::State) -> usize {
+    pub fn add_agent(&mut self, agent: A, state: A::State) -> usize {
+        self.spawn(agent, state, self.tick)
+    }
+
+    /// Adds an agent, which is updated for the first time in `tick`.
+    fn spawn(&mut self, agent: A, mut state: A::State, tick: u64) -> usize {
         let id = self.agents.len();
         agent.on_creation(id, &mut state, &self.world);
         self.agents.push((agent, state));
+        self.scheduler.add_agent(tick);
         id
     }
 
+    /// Removes the agent with the specified `id` and returns it with its state.
+    ///
+    /// [`World::on_death()`] is called for the removed agent, and it's counted as a death. The
+    /// ids of all agents added after it decrease by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn remove_agent(&mut self, id: usize) -> (A, A::State) {
+        let (agent, state) = &self.agents[id];
+        self.world.on_death(id, agent, state);
+        let (agent, state) = self.agents.remove(id);
+        self.scheduler.remove_agent(id);
+        self.population.deaths += 1;
+        (agent, state)
+    }
+
+    /// Returns the number of ticks, the agent with the specified `id` has been simulated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn age(&self, id: usize) -> u64 {
+        self.tick.saturating_sub(self.scheduler.agent(id).born)
+    }
+
+    /// Returns the number of births and deaths so far.
+    #[inline]
+    pub fn population(&self) -> Population {
+        self.population
+    }
+
+    /// Wakes up the agent with the specified `id`, so it will be updated in the next tick.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn wake(&mut self, id: usize) {
+        self.scheduler.set_next_update(id, self.tick);
+    }
+
+    /// Returns the tick, in which the agent with the specified `id` is updated next.
+    ///
+    /// Returns `None`, if the agent is suspended.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn next_update(&self, id: usize) -> Option<u64> {
+        self.scheduler.next_update(id, self.tick)
+    }
+
+    /// Returns the period, in which the agent with the specified `id` is updated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn agent_period(&self, id: usize) -> Period {
+        self.scheduler.agent(id).period
+    }
+
+    /// Sets the period, in which the agent with the specified `id` is updated.
+    ///
+    /// An agent is only updated in ticks, which are due in its period. When an agent wakes up in
+    /// a tick, which is not due, it is updated in the next due tick instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no agent with the specified `id` exists.
+    pub fn set_agent_period(&mut self, id: usize, period: Period) {
+        self.scheduler.set_period(id, period);
+    }
+
+    /// Sets the period for a group of agents.
+    ///
+    /// This is the same as calling [`Simulation::set_agent_period()`] for every id in `group`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any agent in `group` does not exist.
+    pub fn set_group_period(&mut self, group: impl IntoIterator<Item = usize>, period: Period) {
+        for id in group {
+            self.set_agent_period(id, period);
+        }
+    }
+
+    /// Returns the period, in which [`World::update()`] is invoked.
+    pub fn world_period(&self) -> Period {
+        self.scheduler.world_period()
+    }
+
+    /// Sets the period, in which [`World::update()`] is invoked.
+    ///
+    /// By default, the world is updated in every tick. World updates don't count as events for
+    /// [`Simulation::next_event()`].
+    pub fn set_world_period(&mut self, period: Period) {
+        self.scheduler.set_world_period(period);
+    }
+
+    /// Registers an invariant, which has to hold after every tick.
+    ///
+    /// The `check` returns the ids of the offending agents as error, if the invariant is violated.
+    /// In debug builds, all invariants are checked after every call to [`Simulation::update()`]
+    /// and a violation panics with the tick, the `name` of the invariant and the offending
+    /// agents. In release builds, invariants are only checked by
+    /// [`Simulation::check_invariants()`].
+    pub fn add_invariant<F>(&mut self, name: impl Into<String>, check: F)
+    where
+        F: Fn(&A::World, &[(A, A::State)]) -> Result<(), Vec<usize>> + Send + Sync + 'static,
+    {
+        self.invariants.push(Invariant {
+            name: name.into(),
+            check: Box::new(check),
+        });
+    }
+
+    /// Checks all registered invariants in the order they were added.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violated invariant.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        for invariant in &self.invariants {
+            (invariant.check)(&self.world, &self.agents).map_err(|agents| InvariantViolation {
+                tick: self.tick,
+                name: invariant.name.clone(),
+                agents,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the context of the tick, which is simulated next.
+    pub fn tick_context(&self) -> TickContext {
+        self.scheduler.context(self.tick, self.seed)
+    }
+
+    /// Returns the seed of the simulation.
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sets the seed of the simulation, which is passed to the world in the [`TickContext`].
+    ///
+    /// See [`TickContext::seed_for()`] for deriving random number generators from the seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Returns the maximum number of ticks kept in the history.
+    pub fn history_capacity(&self) -> usize {
+        self.history.capacity()
+    }
+
+    /// Returns the number of ticks currently stored in the history.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Restores the state of the simulation from a snapshot.
+    ///
+    /// This replaces the tick, the world, all agents, their schedules, and the population
+    /// counters. Scheduled [`Event`]s are restored as well. The history is left untouched.
+    ///
+    /// [`Event`]: crate::Event
+    pub fn restore(&mut self, snapshot: Snapshot<A>) {
+        self.tick = snapshot.tick;
+        self.world = snapshot.world;
+        self.agents = snapshot.agents;
+        self.scheduler = snapshot.scheduler;
+        self.population = snapshot.population;
+    }
+
+    /// Restores the state of the simulation `ticks` ticks ago.
+    ///
+    /// The rewound ticks are removed from the history. If the history contains less than `ticks`
+    /// ticks, the simulation is rewound to the oldest tick in the history. Returns the number of
+    /// rewound ticks.
+    pub fn rewind(&mut self, ticks: u64) -> u64 {
+        let ticks = usize::try_from(ticks).unwrap_or(usize::MAX);
+        let rewound = ticks.min(self.history.len());
+        if let Some(snapshot) = self.history.pop(rewound) {
+            self.restore(snapshot);
+        }
+        self.history.rewound += rewound as u64;
+        rewound as u64
+    }
+
+    /// Schedules an event to be executed at the beginning of the specified `tick`.
+    ///
+    /// Events are executed before any agent is updated in that tick. Events scheduled for the
+    /// same tick are executed in the order they were scheduled. An event scheduled for a tick,
+    /// which was already simulated, is executed in the next tick.
+    pub fn schedule<E>(&mut self, tick: u64, event: E)
+    where
+        E: Fn(&mut A::World, &mut [(A, A::State)]) + Send + Sync + 'static,
+    {
+        self.scheduler.schedule(tick, Arc::new(event));
+    }
+
+    /// Returns the next tick, in which an agent is updated or an event is executed.
+    ///
+    /// Returns `None`, if all agents are suspended and no event is scheduled.
+    pub fn next_event(&self) -> Option<u64> {
+        self.scheduler.next_event(self.tick)
+    }
+
+    /// Skips all ticks, in which nothing would happen.
+    ///
+    /// The next call to [`Simulation::update()`] will simulate the tick returned by
+    /// [`Simulation::next_event()`]. Note, that [`World::update()`] is not invoked for skipped
+    /// ticks. Returns the new tick or `None`, if nothing is scheduled.
+    pub fn skip_to_next_event(&mut self) -> Option<u64> {
+        let next = self.next_event()?;
+        self.tick = next;
+        Some(next)
+    }
+
     /// Get a shared reference to the global state.
     pub fn world(&self) -> &A::World {
         &self.world
@@ -68,6 +342,96 @@ impl<A: Agent> Simulation<A> {
     pub fn world_mut(&mut self) -> &mut A::World {
         &mut self.world
     }
+
+    /// Returns the radius used in the interaction phase.
+    #[inline]
+    pub fn interaction_radius(&self) -> Option<f32> {
+        self.interaction_radius
+    }
+
+    /// Sets the radius used in the interaction phase.
+    ///
+    /// When a radius is set, every pair of agents, whose [`Agent::position()`] is within the
+    /// radius, interacts once per tick. `None` disables the interaction phase, which is the
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the radius is not a positive, finite number.
+    pub fn set_interaction_radius(&mut self, radius: Option<f32>) {
+        if let Some(radius) = radius {
+            self.grid.reset(radius);
+        }
+        self.interaction_radius = radius;
+    }
+
+    /// Returns all pairs of agents within the interaction radius.
+    ///
+    /// Every pair `(a, b)` is listed once with `a < b` in ascending order. If no interaction
+    /// radius is set, the list is empty.
+    pub fn interacting_pairs(&mut self) -> Vec<(usize, usize)> {
+        let radius = match self.interaction_radius {
+            Some(radius) => radius,
+            None => return Vec::new(),
+        };
+        self.grid.clear();
+        for (id, (agent, state)) in self.agents.iter().enumerate() {
+            if let Some(position) = agent.position(state) {
+                self.grid.insert(id, position);
+            }
+        }
+        self.grid.pairs(radius)
+    }
+
+    /// Removes the dying agents and adds the children of all agents at the end of a tick.
+    fn lifecycle(&mut self) {
+        let world = &self.world;
+        let fates = self
+            .agents
+            .par_iter()
+            .enumerate()
+            .map(|(id, (agent, state))| {
+                (
+                    agent.dies(id, state, world),
+                    agent.reproduce(id, state, world),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let dead = fates.iter().map(|(dies, _)| *dies).collect::<Vec<_>>();
+        let deaths = dead.iter().filter(|&&dies| dies).count();
+        if deaths > 0 {
+            for (id, (agent, state)) in self.agents.iter().enumerate() {
+                if dead[id] {
+                    self.world.on_death(id, agent, state);
+                }
+            }
+            let mut flags = dead.iter();
+            self.agents
+                .retain(|_| !flags.next().copied().unwrap_or(false));
+            self.scheduler.remove_agents(&dead);
+            self.population.deaths += deaths as u64;
+        }
+
+        for (agent, state) in fates.into_iter().flat_map(|(_, children)| children) {
+            self.spawn(agent, state, self.tick + 1);
+            self.population.births += 1;
+        }
+    }
+
+    /// Runs the interaction phase.
+    ///
+    /// For every pair of agents within the interaction radius [`Agent::on_interact()`] and
+    /// [`World::interact()`] are called.
+    fn interact(&mut self) {
+        for (a, b) in self.interacting_pairs() {
+            let (left, right) = self.agents.split_at_mut(b);
+            let (agent, state) = &mut left[a];
+            let (other, other_state) = &mut right[0];
+            agent.on_interact(a, state, other, b, other_state, &self.world);
+            self.world.interact(a, b, &mut self.agents);
+        }
+    }
 }
 
 impl<A: Agent> Simulation<A>
@@ -75,11 +439,85 @@ where
     A: Clone,
     A::State: Clone,
 {
+    /// Returns a snapshot of the current state of the simulation.
+    pub fn snapshot(&self) -> Snapshot<A>
+    where
+        A::World: Clone,
+    {
+        Snapshot {
+            tick: self.tick,
+            world: self.world.clone(),
+            agents: self.agents.clone(),
+            scheduler: self.scheduler.clone(),
+            population: self.population,
+        }
+    }
+
+    /// Sets the maximum number of ticks kept in the history.
+    ///
+    /// When the history is enabled, a [`Snapshot`] is stored at the beginning of every tick, so
+    /// the tick can be restored by [`Simulation::rewind()`]. The oldest snapshots are dropped
+    /// once the capacity is reached. A capacity of zero disables the history, which is the
+    /// default.
+    pub fn set_history_capacity(&mut self, capacity: usize)
+    where
+        A::World: Clone,
+    {
+        self.history.set_capacity(capacity);
+        self.history.recorder = if capacity == 0 {
+            None
+        } else {
+            Some(Self::snapshot)
+        };
+    }
+
+    /// Simulates up to `ticks` ticks, which were rewound by [`Simulation::rewind()`] before.
+    ///
+    /// Returns the number of replayed ticks. See [`TickContext::seed_for()`] to make replayed
+    /// ticks identical to the original ones.
+    pub fn replay(&mut self, ticks: u64) -> u64 {
+        let ticks = ticks.min(self.history.rewound);
+        for _ in 0..ticks {
+            self.update();
+        }
+        ticks
+    }
+
+    /// Returns an iterator, which advances the simulation by one tick per item and yields a
+    /// [`Snapshot`] after every tick.
+    ///
+    /// A channel-backed stream of snapshots is provided by [`SimulationRunner::subscribe()`].
+    ///
+    /// [`SimulationRunner::subscribe()`]: crate::SimulationRunner::subscribe()
+    pub fn ticks(&mut self) -> Ticks<'_, A, fn(&Self) -> Snapshot<A>>
+    where
+        A::World: Clone,
+    {
+        Ticks::new(self, Self::snapshot)
+    }
+
+    /// Returns an iterator, which advances the simulation by one tick per item and yields the
+    /// result of `view` after every tick.
+    ///
+    /// Unlike [`Simulation::ticks()`], the simulation is not cloned.
+    pub fn observe<T, F>(&mut self, view: F) -> Ticks<'_, A, F>
+    where
+        F: FnMut(&Self) -> T,
+    {
+        Ticks::new(self, view)
+    }
+
     /// Advances the simulation by one tick.
     ///
-    /// First, on every added Agent, [`Agent::on_update()`] is invoked in arbitrary order.
-    /// This happens in parallel. Afterwards, the global state is updated by calling
-    /// [`World::update()`].
+    /// First, all [`Event`]s scheduled for this tick are executed and [`World::begin_tick()`] is
+    /// called. Then, on every added Agent, which is neither sleeping nor out of its [`Period`],
+    /// [`Agent::on_update()`] is invoked in arbitrary order followed by [`Agent::wakeup()`]. This
+    /// happens in parallel. If an interaction radius is set, every pair of agents within this
+    /// radius interacts by calling [`Agent::on_interact()`] and [`World::interact()`]
+    /// sequentially. Afterwards, the global state is updated by calling [`World::update()`], if the
+    /// world period is due. Then, every agent, which [`Agent::dies()`], is removed, and the
+    /// children returned by [`Agent::reproduce()`] are added. In debug builds, all invariants are
+    /// checked at the end of the tick.
     ///
     /// To every [`Agent`] it's current state is passed as unique reference. Also a list
     /// of all other agents is passed as shared reference. The list is updated once before
@@ -87,17 +525,51 @@ where
     ///
     /// When updating the global state, a mutable slice to all `Agent`s and its states
     /// are passed to [`World`].
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, this panics if an invariant registered with
+    /// [`Simulation::add_invariant()`] is violated.
+    ///
+    /// [`Event`]: crate::Event
     pub fn update(&mut self) {
+        if let Some(record) = self.history.recorder {
+            let snapshot = record(self);
+            self.history.push(snapshot);
+        }
+        self.history.rewound = self.history.rewound.saturating_sub(1);
+
+        let tick = self.tick;
+        while let Some(event) = self.scheduler.pop_event(tick) {
+            event(&mut self.world, &mut self.agents);
+        }
+        let context = self.scheduler.context(tick, self.seed);
+        self.world.begin_tick(&context);
+
         self.state_buffer.clone_from(&self.agents);
         let state_buffer = &self.state_buffer;
         let world = &self.world;
         self.agents
             .par_iter_mut()
+            .zip(self.scheduler.agents_mut())
             .enumerate()
-            .for_each(|(id, (agent, state))| {
-                agent.on_update(id, state, world, state_buffer);
+            .for_each(|(id, ((agent, state), schedule))| {
+                if schedule.is_due(tick) {
+                    agent.on_update(id, state, world, state_buffer);
+                    schedule.next_update = agent.wakeup(id, state, world).next_update(tick);
+                }
             });
-        self.world.update(&mut self.agents);
+        self.interact();
+        if context.world_due {
+            self.world.update(&mut self.agents);
+        }
+        self.lifecycle();
+        self.tick += 1;
+
+        #[cfg(debug_assertions)]
+        if let Err(violation) = self.check_invariants() {
+            panic!("{}", violation);
+        }
     }
 }
 
@@ -105,7 +577,7 @@ where
 mod tests {
     use std::sync::atomic::{AtomicU64, Ordering};
 
-    use crate::{Agent, Simulation, World};
+    use crate::{Agent, Period, Population, Simulation, TickContext, Wakeup, World};
 
     #[derive(Debug, PartialEq, Eq)]
     struct SimpleWorld(&'static str);
@@ -185,6 +657,332 @@ mod tests {
         assert_eq!(agent.on_update_count.load(Ordering::SeqCst), 1);
     }
 
+    #[derive(Clone)]
+    struct PointAgent;
+    impl Agent for PointAgent {
+        type State = (f32, Vec<usize>);
+        type World = Vec<(usize, usize)>;
+
+        fn position(&self, state: &Self::State) -> Option<(f32, f32)> {
+            Some((state.0, 0.))
+        }
+
+        fn on_interact(
+            &self,
+            id: usize,
+            state: &mut Self::State,
+            _other: &Self,
+            other_id: usize,
+            other_state: &mut Self::State,
+            _world: &Self::World,
+        ) {
+            state.1.push(other_id);
+            other_state.1.push(id);
+        }
+    }
+    impl World<PointAgent> for Vec<(usize, usize)> {
+        fn interact(
+            &mut self,
+            a: usize,
+            b: usize,
+            _agents: &mut [(PointAgent, (f32, Vec<usize>))],
+        ) {
+            self.push((a, b));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_interaction() {
+        let mut simulation = Simulation::new(Vec::new());
+        for x in &[0., 5., 1., 1.5, 10.] {
+            simulation.add_agent(PointAgent, (*x, Vec::new()));
+        }
+
+        simulation.update();
+        assert!(simulation.world().is_empty());
+
+        simulation.set_interaction_radius(Some(1.));
+        assert_eq!(simulation.interacting_pairs(), vec![(0, 2), (2, 3)]);
+        simulation.update();
+        assert_eq!(*simulation.world(), vec![(0, 2), (2, 3)]);
+
+        let interactions = simulation
+            .agents()
+            .iter()
+            .map(|(_, (_, others))| others.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            interactions,
+            vec![vec![2], vec![], vec![0, 3], vec![2], vec![]]
+        );
+    }
+
+    /// Records the ticks it was updated in and sleeps for the stored number of ticks afterwards.
+    #[derive(Clone)]
+    struct SleepyAgent(u64);
+    impl Agent for SleepyAgent {
+        type State = Vec<u64>;
+        type World = u64;
+
+        fn on_update(
+            &self,
+            _id: usize,
+            state: &mut Self::State,
+            world: &Self::World,
+            _population: &[(Self, Self::State)],
+        ) {
+            state.push(*world);
+        }
+
+        fn wakeup(&self, _id: usize, _state: &Self::State, _world: &Self::World) -> Wakeup {
+            match self.0 {
+                0 => Wakeup::Suspend,
+                ticks => Wakeup::In(ticks),
+            }
+        }
+    }
+    impl World<SleepyAgent> for u64 {
+        fn update(&mut self, _agents: &mut [(SleepyAgent, Vec<u64>)]) {
+            *self += 1;
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_wakeup() {
+        let mut simulation = Simulation::new(0);
+        for ticks in &[1, 3, 0] {
+            simulation.add_agent(SleepyAgent(*ticks), Vec::new());
+        }
+
+        for _ in 0..7 {
+            simulation.update();
+        }
+        assert_eq!(simulation.tick(), 7);
+        assert_eq!(simulation.agents()[0].1, vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(simulation.agents()[1].1, vec![0, 3, 6]);
+        assert_eq!(simulation.agents()[2].1, vec![0]);
+        assert_eq!(simulation.next_update(1), Some(9));
+        assert_eq!(simulation.next_update(2), None);
+
+        simulation.wake(2);
+        assert_eq!(simulation.next_update(2), Some(7));
+        simulation.update();
+        assert_eq!(simulation.agents()[2].1, vec![0, 7]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_events() {
+        let mut simulation = Simulation::new(0);
+        simulation.add_agent(SleepyAgent(0), Vec::new());
+        simulation.update();
+        assert_eq!(simulation.next_event(), None);
+        assert_eq!(simulation.skip_to_next_event(), None);
+
+        simulation.schedule(20, |world: &mut u64, _agents: &mut [_]| *world += 100);
+        simulation.schedule(10, |world: &mut u64, _agents: &mut [_]| *world *= 2);
+        simulation.schedule(20, |_world: &mut u64, agents: &mut [(SleepyAgent, _)]| {
+            agents[0].0 .0 = 5;
+        });
+
+        assert_eq!(simulation.next_event(), Some(10));
+        assert_eq!(simulation.skip_to_next_event(), Some(10));
+        simulation.update();
+        assert_eq!(*simulation.world(), 3);
+
+        assert_eq!(simulation.skip_to_next_event(), Some(20));
+        simulation.wake(0);
+        simulation.update();
+        assert_eq!(*simulation.world(), 104);
+        assert_eq!(simulation.agents()[0].1, vec![0, 103]);
+        assert_eq!(simulation.next_event(), Some(25));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_periods() {
+        let mut simulation = Simulation::new(0);
+        for _ in 0..3 {
+            simulation.add_agent(SleepyAgent(1), Vec::new());
+        }
+        simulation.set_agent_period(1, Period::new(5, 2));
+        simulation.set_group_period(vec![2], Period::new(3, 0));
+        simulation.set_world_period(Period::new(2, 1));
+
+        let context = simulation.tick_context();
+        assert_eq!(context.tick, 0);
+        assert_eq!(context.due_agents, 2);
+        assert!(!context.world_due);
+
+        for _ in 0..10 {
+            simulation.update();
+        }
+        assert_eq!(*simulation.world(), 5);
+        assert_eq!(simulation.agents()[0].1, vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4]);
+        assert_eq!(simulation.agents()[1].1, vec![1, 3]);
+        assert_eq!(simulation.agents()[2].1, vec![0, 1, 3, 4]);
+        assert_eq!(simulation.next_update(1), Some(12));
+        assert_eq!(simulation.next_event(), Some(10));
+
+        assert_eq!(Period::new(5, 7), Period::new(5, 2));
+        assert_eq!(Period::new(5, 2).next_due(2), 2);
+        assert_eq!(Period::new(5, 2).next_due(3), 7);
+        assert_eq!(Period::EVERY_TICK.next_due(3), 3);
+    }
+
+    fn no_sleepy_world(world: &u64, agents: &[(SleepyAgent, Vec<u64>)]) -> Result<(), Vec<usize>> {
+        let offending = agents
+            .iter()
+            .enumerate()
+            .filter(|(_, (agent, _))| agent.0 < *world)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        if offending.is_empty() {
+            Ok(())
+        } else {
+            Err(offending)
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_invariants() {
+        let mut simulation = Simulation::new(0);
+        for ticks in &[4, 1, 3, 2] {
+            simulation.add_agent(SleepyAgent(*ticks), Vec::new());
+        }
+        simulation.add_invariant("world is counting", |_, _| Ok(()));
+        simulation.add_invariant("agents are awake", no_sleepy_world);
+        assert_eq!(simulation.check_invariants(), Ok(()));
+
+        *simulation.world_mut() = 3;
+        let violation = simulation.check_invariants().unwrap_err();
+        assert_eq!(violation.tick, 0);
+        assert_eq!(violation.name, "agents are awake");
+        assert_eq!(violation.agents, vec![1, 3]);
+        assert_eq!(
+            violation.to_string(),
+            "invariant `agents are awake` violated at tick 0 by agents [1, 3]"
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[cfg_attr(miri, ignore)]
+    #[should_panic(expected = "invariant `agents are awake` violated at tick 2 by agents [1]")]
+    fn test_invariants_update() {
+        let mut simulation = Simulation::new(0);
+        for ticks in &[4, 1, 3] {
+            simulation.add_agent(SleepyAgent(*ticks), Vec::new());
+        }
+        simulation.add_invariant("agents are awake", no_sleepy_world);
+        for _ in 0..3 {
+            simulation.update();
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct SeededWorld(Option<TickContext>);
+
+    /// Walks a random distance derived from the seed of the tick.
+    #[derive(Clone)]
+    struct RandomWalker;
+    impl Agent for RandomWalker {
+        type State = u64;
+        type World = SeededWorld;
+
+        fn on_update(
+            &self,
+            id: usize,
+            state: &mut Self::State,
+            world: &Self::World,
+            _population: &[(Self, Self::State)],
+        ) {
+            *state += world.0.unwrap().seed_for(id) % 100;
+        }
+    }
+    impl World<RandomWalker> for SeededWorld {
+        fn begin_tick(&mut self, context: &TickContext) {
+            self.0 = Some(*context);
+        }
+    }
+
+    fn positions(simulation: &Simulation<RandomWalker>) -> Vec<u64> {
+        simulation
+            .agents()
+            .iter()
+            .map(|(_, state)| *state)
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_history() {
+        let mut simulation = Simulation::new(SeededWorld::default());
+        simulation.set_seed(42);
+        simulation.set_history_capacity(3);
+        for _ in 0..4 {
+            simulation.add_agent(RandomWalker, 0);
+        }
+
+        let mut states = vec![positions(&simulation)];
+        for _ in 0..5 {
+            simulation.update();
+            states.push(positions(&simulation));
+        }
+        assert_eq!(simulation.history_len(), 3);
+        assert_ne!(states[4], states[5]);
+
+        assert_eq!(simulation.rewind(2), 2);
+        assert_eq!(simulation.tick(), 3);
+        assert_eq!(positions(&simulation), states[3]);
+        assert_eq!(simulation.history_len(), 1);
+
+        assert_eq!(simulation.replay(5), 2);
+        assert_eq!(simulation.tick(), 5);
+        assert_eq!(positions(&simulation), states[5]);
+        assert_eq!(simulation.replay(1), 0);
+
+        assert_eq!(simulation.rewind(10), 3);
+        assert_eq!(simulation.tick(), 2);
+        assert_eq!(positions(&simulation), states[2]);
+
+        let snapshot = simulation.snapshot();
+        simulation.update();
+        simulation.restore(snapshot.clone());
+        assert_eq!(snapshot.tick(), 2);
+        assert_eq!(positions(&simulation), states[2]);
+
+        simulation.set_history_capacity(0);
+        simulation.update();
+        assert_eq!(simulation.history_len(), 0);
+        assert_eq!(simulation.rewind(1), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_ticks() {
+        let mut simulation = Simulation::new(0);
+        simulation.add_agent(SleepyAgent(2), Vec::new());
+
+        let worlds = simulation
+            .ticks()
+            .take(3)
+            .map(|snapshot| (snapshot.tick(), *snapshot.world()))
+            .collect::<Vec<_>>();
+        assert_eq!(worlds, vec![(1, 1), (2, 2), (3, 3)]);
+
+        let updates = simulation
+            .observe(|simulation| simulation.agents()[0].1.len())
+            .every(4)
+            .until(|updates| *updates >= 5)
+            .collect::<Vec<_>>();
+        assert_eq!(updates, vec![4, 6]);
+        assert_eq!(simulation.tick(), 11);
+    }
+
     #[test]
     fn test_callback() {
         let agent = &CountingAgent::default();
@@ -199,4 +997,91 @@ mod tests {
 
         assert_eq!(agent.on_creation_count.load(Ordering::Relaxed), 2);
     }
+
+    /// A rabbit, which counts its updates, has a child every third update, and dies after five.
+    /// The state is the age and the generation.
+    #[derive(Clone)]
+    struct Rabbit;
+
+    /// Records the id and the generation of every dead rabbit.
+    impl World<Rabbit> for Vec<(usize, u64)> {
+        fn on_death(&mut self, id: usize, _agent: &Rabbit, state: &(u64, u64)) {
+            self.push((id, state.1));
+        }
+    }
+
+    impl Agent for Rabbit {
+        type State = (u64, u64);
+        type World = Vec<(usize, u64)>;
+
+        fn on_update(
+            &self,
+            _id: usize,
+            state: &mut Self::State,
+            _world: &Self::World,
+            _population: &[(Self, Self::State)],
+        ) {
+            state.0 += 1;
+        }
+
+        fn dies(&self, _id: usize, state: &Self::State, _world: &Self::World) -> bool {
+            state.0 >= 5
+        }
+
+        fn reproduce(
+            &self,
+            _id: usize,
+            state: &Self::State,
+            _world: &Self::World,
+        ) -> Vec<(Self, Self::State)> {
+            if state.0 % 3 == 0 {
+                vec![(Self, (0, state.1 + 1))]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_lifecycle() {
+        let mut simulation = Simulation::new(Vec::new());
+        simulation.add_agent(Rabbit, (0, 0));
+        simulation.set_history_capacity(10);
+
+        let mut populations = Vec::new();
+        for tick in 0..12 {
+            if tick == 1 {
+                simulation.add_agent(Rabbit, (0, 0));
+            }
+            populations.push(simulation.population());
+            simulation.update();
+            for (id, (_, (age, _))) in simulation.agents().iter().enumerate() {
+                assert_eq!(simulation.age(id), *age);
+            }
+        }
+        // The oldest rabbit always dies first
+        assert_eq!(
+            simulation.world(),
+            &[(0, 0), (0, 0), (0, 1), (0, 1), (0, 2), (0, 2)]
+        );
+        assert_eq!(
+            simulation.population(),
+            Population {
+                births: 7,
+                deaths: 6,
+            }
+        );
+        assert_eq!(simulation.agents().len(), 3);
+
+        assert_eq!(simulation.rewind(4), 4);
+        assert_eq!(simulation.population(), populations[8]);
+        assert_eq!(simulation.replay(4), 4);
+        assert_eq!(simulation.population().births, 7);
+
+        let (_, state) = simulation.remove_agent(1);
+        assert_eq!(simulation.world().last(), Some(&(1, state.1)));
+        assert_eq!(simulation.population().deaths, 7);
+        assert_eq!(simulation.agents().len(), 2);
+    }
 }
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+/// A uniform grid to look up agents by their position.
+///
+/// Every position is sorted into a square cell of `cell_size`. Queries for a radius only have to
+/// look into the cells overlapping the radius instead of checking every inserted position.
+/// Queries are fastest, when the cell size matches the queried radius.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    positions: Vec<(usize, (f32, f32))>,
+}
+
+impl SpatialGrid {
+    /// Creates an empty grid with the given cell size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` is not a positive, finite number.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(
+            cell_size.is_finite() && cell_size > 0.,
+            "cell size must be positive and finite"
+        );
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Returns the size of a cell.
+    #[inline]
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Removes all positions while keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.cells.values_mut().for_each(Vec::clear);
+        self.positions.clear();
+    }
+
+    /// Removes all positions and changes the cell size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` is not a positive, finite number.
+    pub fn reset(&mut self, cell_size: f32) {
+        assert!(
+            cell_size.is_finite() && cell_size > 0.,
+            "cell size must be positive and finite"
+        );
+        if (cell_size - self.cell_size).abs() > f32::EPSILON {
+            self.cells.clear();
+        }
+        self.cell_size = cell_size;
+        self.clear();
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn cell(&self, (x, y): (f32, f32)) -> (i64, i64) {
+        (
+            (x / self.cell_size).floor() as i64,
+            (y / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// Inserts the position of the agent with the specified `id`.
+    pub fn insert(&mut self, id: usize, position: (f32, f32)) {
+        let cell = self.cell(position);
+        self.cells
+            .entry(cell)
+            .or_default()
+            .push(self.positions.len());
+        self.positions.push((id, position));
+    }
+
+    /// Returns the ids of all inserted positions within `radius` around `position` in ascending
+    /// order.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn neighbors(&self, position: (f32, f32), radius: f32) -> Vec<usize> {
+        let (cx, cy) = self.cell(position);
+        let range = (radius / self.cell_size).ceil() as i64;
+        let mut neighbors = Vec::new();
+        for x in cx - range..=cx + range {
+            for y in cy - range..=cy + range {
+                for &index in self.cells.get(&(x, y)).into_iter().flatten() {
+                    let (id, other) = self.positions[index];
+                    if distance_squared(position, other) <= radius * radius {
+                        neighbors.push(id);
+                    }
+                }
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    /// Returns every pair of ids, whose positions are within `radius` of each other.
+    ///
+    /// Every pair is listed exactly once as `(a, b)` with `a < b`. The list is sorted, so the
+    /// result does not depend on the insertion order.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn pairs(&self, radius: f32) -> Vec<(usize, usize)> {
+        let range = (radius / self.cell_size).ceil() as i64;
+        let mut pairs = Vec::new();
+        for &(id, position) in &self.positions {
+            let (cx, cy) = self.cell(position);
+            for x in cx - range..=cx + range {
+                for y in cy - range..=cy + range {
+                    for &index in self.cells.get(&(x, y)).into_iter().flatten() {
+                        let (other_id, other) = self.positions[index];
+                        if id < other_id && distance_squared(position, other) <= radius * radius {
+                            pairs.push((id, other_id));
+                        }
+                    }
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+fn distance_squared(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).mul_add(a.0 - b.0, (a.1 - b.1) * (a.1 - b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpatialGrid;
+
+    fn brute_force(positions: &[(f32, f32)], radius: f32) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (a, pa) in positions.iter().enumerate() {
+            for (b, pb) in positions.iter().enumerate().skip(a + 1) {
+                if (pa.0 - pb.0).powi(2) + (pa.1 - pb.1).powi(2) <= radius * radius {
+                    pairs.push((a, b));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_pairs() {
+        let positions = (0..50_u16)
+            .map(|i| (f32::from(i * 7 % 13), f32::from(i * 11 % 17) - 5.))
+            .collect::<Vec<_>>();
+
+        for &cell_size in &[0.5, 1., 2.5, 10.] {
+            let mut grid = SpatialGrid::new(cell_size);
+            for (id, position) in positions.iter().enumerate().rev() {
+                grid.insert(id, *position);
+            }
+            for &radius in &[0., 1., 2., 4.5] {
+                assert_eq!(grid.pairs(radius), brute_force(&positions, radius));
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut grid = SpatialGrid::new(1.);
+        grid.insert(3, (0., 0.));
+        grid.insert(1, (1., 1.));
+        grid.insert(2, (-0.5, 0.));
+        grid.insert(0, (5., 5.));
+
+        assert_eq!(grid.neighbors((0., 0.), 1.), vec![2, 3]);
+        assert_eq!(grid.neighbors((0., 0.), 1.5), vec![1, 2, 3]);
+
+        grid.reset(2.);
+        assert!(grid.neighbors((0., 0.), 10.).is_empty());
+        assert!((grid.cell_size() - 2.).abs() < f32::EPSILON);
+    }
+}
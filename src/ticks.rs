@@ -0,0 +1,92 @@
+use crate::{Agent, Simulation};
+
+/// An iterator, which advances a [`Simulation`] by one tick per item.
+///
+/// Created by [`Simulation::ticks()`] and [`Simulation::observe()`]. Every item is a view of
+/// the simulation after the tick. The iterator never ends on its own, so it should be limited,
+/// e.g. by [`Iterator::take()`] or [`Ticks::until()`].
+pub struct Ticks<'sim, A: Agent, F> {
+    simulation: &'sim mut Simulation<A>,
+    every: u64,
+    view: F,
+}
+
+impl<'sim, A: Agent, F> Ticks<'sim, A, F> {
+    pub(crate) fn new(simulation: &'sim mut Simulation<A>, view: F) -> Self {
+        Self {
+            simulation,
+            every: 1,
+            view,
+        }
+    }
+
+    /// Only yields every `n`th tick.
+    ///
+    /// Unlike [`Iterator::step_by()`], the skipped ticks are simulated without creating a view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn every(mut self, n: u64) -> Self {
+        assert_ne!(n, 0, "cannot sample every 0th tick");
+        self.every = n;
+        self
+    }
+
+    /// Stops after the first item, for which the predicate returns `true`.
+    ///
+    /// Unlike [`Iterator::take_while()`], the matching item is yielded as well.
+    pub fn until<P>(self, predicate: P) -> Until<Self, P>
+    where
+        Self: Iterator,
+        P: FnMut(&<Self as Iterator>::Item) -> bool,
+    {
+        Until {
+            iter: self,
+            predicate,
+            done: false,
+        }
+    }
+}
+
+impl<'sim, A, F, T> Iterator for Ticks<'sim, A, F>
+where
+    A: Agent + Clone,
+    A::State: Clone,
+    F: FnMut(&Simulation<A>) -> T,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        for _ in 0..self.every {
+            self.simulation.update();
+        }
+        Some((self.view)(self.simulation))
+    }
+}
+
+/// An iterator, which stops after the first item matching a predicate.
+///
+/// Created by [`Ticks::until()`].
+pub struct Until<I, P> {
+    iter: I,
+    predicate: P,
+    done: bool,
+}
+
+impl<I, P> Iterator for Until<I, P>
+where
+    I: Iterator,
+    P: FnMut(&I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.iter.next()?;
+        self.done = (self.predicate)(&item);
+        Some(item)
+    }
+}
@@ -1,13 +1,35 @@
-use crate::Agent;
+use crate::{Agent, TickContext};
 
 /// The world holds the global state used in the simulation
 ///
 /// It is updated once in a tick after all [`Agent`] were updated.
 /// The [`World`] is able to mutate all states of any agent.
 pub trait World<A: Agent> {
+    #[allow(unused_variables)]
+    /// Called at the beginning of every tick before any agent is updated.
+    ///
+    /// The `context` describes the upcoming tick, e.g. which updates are due. The world may
+    /// store the information, so agents are able to access them.
+    fn begin_tick(&mut self, context: &TickContext) {}
+
     #[allow(unused_variables)]
     /// The update method called when the global states is going to be updated.
     fn update(&mut self, agents: &mut [(A, A::State)]) {}
+
+    #[allow(unused_variables)]
+    /// Called once for every pair of agents within the interaction radius.
+    ///
+    /// This is invoked right after [`Agent::on_interact()`] for the same pair, `a` is always
+    /// smaller than `b`.
+    fn interact(&mut self, a: usize, b: usize, agents: &mut [(A, A::State)]) {}
+
+    #[allow(unused_variables)]
+    /// Called for every agent, which is removed from the simulation.
+    ///
+    /// This is invoked right before the agent is removed, so `id` is still the id of the agent.
+    /// When several agents die in the same tick, this is called in ascending order of their ids
+    /// before any of them is removed.
+    fn on_death(&mut self, id: usize, agent: &A, state: &A::State) {}
 }
 
 impl<T: Agent> World<T> for () {}